@@ -0,0 +1,50 @@
+//! Compares parsing filenames using a pattern compiled once with compiling the pattern for every filename.
+//!
+//! Run with `just bench`.
+
+use std::time::Duration;
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use file_retention_policy::config::RetentionFilePattern;
+
+const PATTERN: &str = "hub-backup-{year}-{month}-{day}-{hour}-{minutes}-{seconds}.tar.gz";
+const FILENAMES: usize = 1_000_000;
+/// Compiling the regex per filename is too slow for the full set of filenames
+const UNCOMPILED_FILENAMES: usize = 1_000;
+
+fn filenames(count: usize) -> Vec<String> {
+    (0..count)
+        .map(|i| format!("hub-backup-{}-{:02}-{:02}-{:02}-{:02}-{:02}.tar.gz", 2000 + i % 25, 1 + i % 12, 1 + i % 28, i % 24, i % 60, (i / 60) % 60))
+        .collect()
+}
+
+fn parse(c: &mut Criterion) {
+    let pattern = RetentionFilePattern(PATTERN.to_string());
+    let mut group = c.benchmark_group("parse");
+    group.sample_size(10);
+    group.measurement_time(Duration::from_secs(15));
+
+    let filenames = filenames(FILENAMES);
+    group.throughput(Throughput::Elements(FILENAMES as u64));
+    group.bench_function("compiled once", |b| {
+        b.iter(|| {
+            let compiled = pattern.compile().unwrap();
+            filenames.iter().for_each(|filename| {
+                compiled.parse(filename).unwrap();
+            })
+        })
+    });
+
+    let filenames = &filenames[..UNCOMPILED_FILENAMES];
+    group.throughput(Throughput::Elements(UNCOMPILED_FILENAMES as u64));
+    group.bench_function("compiled per filename", |b| {
+        b.iter(|| filenames.iter().for_each(|filename| {
+            pattern.parse(filename).unwrap();
+        }))
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, parse);
+criterion_main!(benches);
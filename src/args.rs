@@ -1,25 +1,244 @@
 use clap::{Parser, Subcommand};
-use std::path::PathBuf;
+use color_eyre::eyre::eyre;
+use std::convert::Infallible;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use file_retention_policy::config::{ConfigFormat, DeletionOptions, RetentionFilePattern, RetentionPolicy};
+use file_retention_policy::executor::{ExecutionContext, STDIN_INPUT};
+use file_retention_policy::listing::ListingFormat;
+
+use crate::Result;
 
 #[derive(Debug, Clone, Parser)]
 #[command(author, version, about)]
 pub struct Args {
     /// Config File
+    ///
+    /// Defaults to $FILE_RETENTION_CONFIG, $XDG_CONFIG_HOME/file-retention-policy/config.toml or /etc/file-retention-policy/config.toml
     #[arg(short, long)]
     pub config: Option<PathBuf>,
+    /// Format of the config file (toml, yaml or json), detected by the file extension by default
+    #[arg(long)]
+    pub config_format: Option<ConfigFormat>,
     #[command(subcommand)]
     pub command: Option<SubCommand>,
     #[arg(short, long, default_value_t = false)]
     pub dry_run: bool,
+    /// Only process the paths with the given name or path
+    #[arg(long, value_name = "NAME|PATH")]
+    pub only: Vec<String>,
+    /// Skip the paths with the given name or path
+    #[arg(long, value_name = "NAME|PATH")]
+    pub skip: Vec<String>,
+    /// Amount of paths to process concurrently. Paths pointing to the same directory are never processed concurrently
+    #[arg(short, long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    pub jobs: u16,
+}
+
+impl Args {
+    /// Resolves the simulation inputs against the positional path.
+    pub fn execution_context(&self) -> Result<ExecutionContext> {
+        if let Some(SubCommand::Simulate { path, input, input_format, read_missing }) = self.command.clone() {
+            let inputs = input.into_iter()
+                .map(|input| {
+                    let selector = input.path
+                        .or_else(|| path.as_ref().map(|path| path.to_string_lossy().to_string()))
+                        .ok_or_else(|| eyre!("Input {:?} has no path, use <path>=<file> or pass the path as argument", input.input))?;
+
+                    Ok((selector, input.input))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            if inputs.iter().filter(|(_, input)| input == Path::new(STDIN_INPUT)).count() > 1 {
+                return Err(eyre!("Only one input can be read from stdin"));
+            }
+
+            Ok(ExecutionContext::Simulate {
+                read_missing: read_missing || inputs.is_empty(),
+                inputs,
+                input_format,
+            })
+        } else if self.dry_run {
+            Ok(ExecutionContext::DryRun)
+        } else {
+            Ok(ExecutionContext::Default)
+        }
+    }
 }
 
 #[derive(Debug, Clone, Subcommand)]
 pub enum SubCommand {
     Simulate {
-        /// Path to simulate retention policy for
+        /// Path to simulate retention policy for, used for inputs without a path
+        path: Option<PathBuf>,
+        /// Textfile with one filename per line, either as `<file>` or `<name|path>=<file>`. Use `-` to read from stdin
+        #[arg(long)]
+        input: Vec<SimulationInput>,
+        /// Format of the inputs: lines, ls (`ls -l --time-style=full-iso`), find (`find -printf '%f,%s,%T@\n'`) or json (lines with name, size and mtime).
+        /// Detected by the first line by default
+        #[arg(long, default_value = "auto")]
+        input_format: ListingFormat,
+        /// Read the directories of paths without an input instead of treating them as empty
+        #[arg(long, default_value_t = false)]
+        read_missing: bool,
+    },
+    /// Validate the config and report how well the file patterns match the existing files
+    Check,
+    /// Apply a retention policy to a single directory without a config file
+    Prune {
+        /// Directory containing the backups
         path: PathBuf,
-        /// Textfile with one filename per line
+        /// File pattern to extract the date from the file names, e.g. '{year}-{month}-{day}.tar'
         #[arg(long)]
-        input: Option<PathBuf>
+        pattern: RetentionFilePattern,
+        #[command(flatten)]
+        retention: RetentionArgs,
+        #[command(flatten)]
+        deletion: DeletionArgs,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimulationInput {
+    /// Name or path of the configured path
+    pub path: Option<String>,
+    pub input: PathBuf,
+}
+
+impl FromStr for SimulationInput {
+    type Err = Infallible;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let input = match value.split_once('=') {
+            Some((path, input)) => SimulationInput {
+                path: Some(path.to_string()),
+                input: PathBuf::from(input),
+            },
+            None => SimulationInput {
+                path: None,
+                input: PathBuf::from(value),
+            },
+        };
+
+        Ok(input)
+    }
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct RetentionArgs {
+    /// Keep the last N backups
+    #[arg(long, value_name = "N")]
+    pub keep_last: Option<usize>,
+    /// Keep backups for the last N hours
+    #[arg(long, value_name = "N")]
+    pub keep_hourly: Option<usize>,
+    /// Keep backups for the last N days
+    #[arg(long, value_name = "N")]
+    pub keep_daily: Option<usize>,
+    /// Keep backups for the last N weeks
+    #[arg(long, value_name = "N")]
+    pub keep_weekly: Option<usize>,
+    /// Keep backups for the last N months
+    #[arg(long, value_name = "N")]
+    pub keep_monthly: Option<usize>,
+    /// Keep backups for the last N years
+    #[arg(long, value_name = "N")]
+    pub keep_yearly: Option<usize>,
+}
+
+impl From<RetentionArgs> for RetentionPolicy {
+    fn from(args: RetentionArgs) -> Self {
+        RetentionPolicy {
+            keep_last: args.keep_last,
+            keep_hourly: args.keep_hourly,
+            keep_daily: args.keep_daily,
+            keep_weekly: args.keep_weekly,
+            keep_monthly: args.keep_monthly,
+            keep_yearly: args.keep_yearly,
+        }
+    }
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct DeletionArgs {
+    /// Amount of files to delete concurrently
+    #[arg(long, value_name = "N")]
+    pub delete_concurrency: Option<NonZeroUsize>,
+    /// Maximum amount of deletions per second
+    #[arg(long, value_name = "N")]
+    pub delete_rate_limit: Option<u32>,
+}
+
+impl From<DeletionArgs> for DeletionOptions {
+    fn from(args: DeletionArgs) -> Self {
+        DeletionOptions {
+            concurrency: args.delete_concurrency,
+            rate_limit: args.delete_rate_limit,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    #[test]
+    fn prune_should_parse_retention() {
+        let args = Args::try_parse_from(["file-retention-policy", "--dry-run", "prune", "/var/backups/pg", "--pattern", "{year}-{month}-{day}.tar", "--keep-daily", "7", "--keep-monthly", "6"]).unwrap();
+
+        let Some(SubCommand::Prune { path, pattern, retention, .. }) = args.command else {
+            panic!("Expected prune subcommand");
+        };
+        assert!(args.dry_run);
+        assert_eq!(PathBuf::from("/var/backups/pg"), path);
+        assert_eq!("{year}-{month}-{day}.tar", pattern.0);
+        assert_eq!(RetentionPolicy {
+            keep_daily: Some(7),
+            keep_monthly: Some(6),
+            ..Default::default()
+        }, retention.into());
+    }
+
+    #[test]
+    fn prune_should_parse_deletion() {
+        let args = Args::try_parse_from(["file-retention-policy", "prune", "/var/backups/pg", "--pattern", "{year}", "--delete-concurrency", "4", "--delete-rate-limit", "50"]).unwrap();
+
+        let Some(SubCommand::Prune { deletion, .. }) = args.command else {
+            panic!("Expected prune subcommand");
+        };
+        assert_eq!(DeletionOptions {
+            concurrency: NonZeroUsize::new(4),
+            rate_limit: Some(50),
+        }, deletion.into());
+    }
+
+    #[test]
+    fn simulate_should_parse_multiple_inputs() {
+        let args = Args::try_parse_from(["file-retention-policy", "simulate", "--input", "/var/backups/pg=pg.lines", "--input", "hub=-", "--read-missing"]).unwrap();
+
+        let Some(SubCommand::Simulate { path, input, read_missing, .. }) = args.command else {
+            panic!("Expected simulate subcommand");
+        };
+        assert_eq!(None, path);
+        assert!(read_missing);
+        assert_eq!(vec![
+            SimulationInput { path: Some("/var/backups/pg".into()), input: PathBuf::from("pg.lines") },
+            SimulationInput { path: Some("hub".into()), input: PathBuf::from("-") },
+        ], input);
+    }
+
+    #[test]
+    fn simulate_should_parse_legacy_input() {
+        let args = Args::try_parse_from(["file-retention-policy", "simulate", "/var/backups/pg", "--input", "pg.lines"]).unwrap();
+
+        let Some(SubCommand::Simulate { path, input, read_missing, .. }) = args.command else {
+            panic!("Expected simulate subcommand");
+        };
+        assert_eq!(Some(PathBuf::from("/var/backups/pg")), path);
+        assert!(!read_missing);
+        assert_eq!(vec![SimulationInput { path: None, input: PathBuf::from("pg.lines") }], input);
     }
 }
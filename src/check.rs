@@ -0,0 +1,92 @@
+use color_eyre::eyre::eyre;
+
+use file_retention_policy::config::{Config, RetentionPath};
+use file_retention_policy::storage;
+use crate::Result;
+
+/// Amount of directory entries to sample when calculating the match rate.
+const SAMPLE_SIZE: usize = 1000;
+/// Amount of non-matching file names to list per path.
+const MAX_EXAMPLES: usize = 5;
+
+pub fn check(config: &Config) -> Result<()> {
+    let mut valid = true;
+    for path in &config.paths {
+        let span = tracing::info_span!("check", name = %path.name());
+        let _enter = span.enter();
+
+        match config.policy_for(path) {
+            Ok(policy) => tracing::info!("Effective retention: {policy}"),
+            Err(err) => {
+                tracing::error!("{err}");
+                valid = false;
+            }
+        }
+        valid &= check_path(path);
+    }
+
+    if valid {
+        tracing::info!("Config is valid");
+        Ok(())
+    } else {
+        Err(eyre!("Config contains invalid paths"))
+    }
+}
+
+fn check_path(path: &RetentionPath) -> bool {
+    let pattern = path.file_pattern.pattern();
+    let issues = pattern.validate();
+    for issue in &issues {
+        tracing::error!(pattern = pattern.0, "{issue}");
+    }
+    // Sampling files is pointless while the pattern is invalid
+    if !issues.is_empty() {
+        return false;
+    }
+
+    let storage = match storage::open(&path.path) {
+        Ok(storage) => storage,
+        Err(err) => {
+            tracing::error!("{err}");
+            return false;
+        }
+    };
+    let entries = match storage.list() {
+        Ok(entries) => entries,
+        Err(err) => {
+            tracing::error!("{err}");
+            return false;
+        }
+    };
+    let mut sampled = 0;
+    let mut matched = 0;
+    let mut unmatched = Vec::new();
+    let mut invalid_dates = Vec::new();
+    for entry in entries.take(SAMPLE_SIZE) {
+        let filename = entry.name;
+        sampled += 1;
+        match path.file_pattern.parse(&filename) {
+            Ok(_) => matched += 1,
+            Err(err) if err.is_invalid_date() => invalid_dates.push(filename),
+            Err(_) if unmatched.len() < MAX_EXAMPLES => unmatched.push(filename),
+            Err(_) => {}
+        }
+    }
+    if !invalid_dates.is_empty() {
+        tracing::warn!(?invalid_dates, "Files match the file pattern but contain invalid dates, they are never deleted");
+    }
+
+    if sampled == 0 {
+        tracing::info!("Directory is empty");
+    } else if matched == 0 {
+        tracing::warn!(?unmatched, "None of {sampled} sampled files match the file pattern");
+    } else {
+        let rate = matched as f64 / sampled as f64 * 100.0;
+        tracing::info!("{matched} of {sampled} sampled files match the file pattern ({rate:.1}%)");
+        if !unmatched.is_empty() {
+            tracing::info!(?unmatched, "Some files don't match the file pattern");
+        }
+    }
+
+    true
+}
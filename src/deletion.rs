@@ -0,0 +1,253 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config::DeletionOptions;
+use crate::file::RetentionFile;
+use crate::storage::{Storage, StorageError};
+
+type Result<T> = std::result::Result<T, StorageError>;
+
+impl DeletionOptions {
+    /// Merges the given options into these, fields set in `overrides` take precedence.
+    pub fn merge(&self, overrides: &DeletionOptions) -> DeletionOptions {
+        DeletionOptions {
+            concurrency: overrides.concurrency.or(self.concurrency),
+            rate_limit: overrides.rate_limit.or(self.rate_limit),
+        }
+    }
+}
+
+/// Deletes files of a single path, optionally using multiple threads and limiting the deletions per second.
+pub struct Deleter {
+    concurrency: usize,
+    limiter: Option<RateLimiter>,
+}
+
+impl Deleter {
+    pub fn new(options: DeletionOptions) -> Self {
+        Self {
+            concurrency: options.concurrency.map(usize::from).unwrap_or(1),
+            limiter: options.rate_limit.filter(|rate| *rate > 0).map(RateLimiter::new),
+        }
+    }
+
+    /// Removes the given files from the storage, stopping at the first error.
+    ///
+    /// Files are removed in batches of [Storage::batch_size], but never more files than the rate limit allows per second.
+    /// Every file counts for the rate limit, no matter how many files are removed by a single request.
+    /// Files which don't exist anymore are skipped.
+    pub fn delete(&self, storage: &dyn Storage, files: &[RetentionFile]) -> Result<()> {
+        let batch_size = match self.limiter.as_ref() {
+            Some(limiter) => storage.batch_size().min(limiter.rate as usize),
+            None => storage.batch_size(),
+        };
+        let batches: Vec<Vec<&str>> = files.chunks(batch_size.max(1))
+            .map(|batch| batch.iter().map(|file| file.filename.as_str()).collect())
+            .collect();
+        let next = AtomicUsize::new(0);
+        let work = || -> Result<()> {
+            while let Some(batch) = batches.get(next.fetch_add(1, Ordering::Relaxed)) {
+                if let Err(err) = self.delete_batch(storage, batch) {
+                    // Stop the other workers from picking up more batches
+                    next.store(batches.len(), Ordering::Relaxed);
+                    return Err(err);
+                }
+            }
+            Ok(())
+        };
+
+        let workers = self.concurrency.min(batches.len());
+        if workers <= 1 {
+            return work();
+        }
+        thread::scope(|scope| {
+            let workers: Vec<_> = (0..workers).map(|_| scope.spawn(work)).collect();
+
+            workers.into_iter().try_for_each(|worker| worker.join().expect("deletion worker panicked"))
+        })
+    }
+
+    fn delete_batch(&self, storage: &dyn Storage, filenames: &[&str]) -> Result<()> {
+        if let Some(limiter) = self.limiter.as_ref() {
+            limiter.acquire(filenames.len() as u32);
+        }
+        tracing::trace!(?filenames, "Deleting files");
+
+        storage.remove_all(filenames)
+    }
+}
+
+/// Spaces operations evenly so no more than `rate` operations start per second.
+struct RateLimiter {
+    rate: u32,
+    interval: Duration,
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    fn new(rate: u32) -> Self {
+        Self {
+            rate,
+            interval: Duration::from_secs(1) / rate,
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Blocks until the next `count` operations may start.
+    fn acquire(&self, count: u32) {
+        let now = Instant::now();
+        let slot = {
+            let mut next = self.next.lock().unwrap();
+            let slot = (*next).max(now);
+            *next = slot + self.interval * count;
+            slot
+        };
+        thread::sleep(slot.saturating_duration_since(now));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::num::NonZeroUsize;
+    use std::path::Path;
+
+    use test_case::test_case;
+
+    use crate::file::FileEntry;
+    use crate::storage::LocalStorage;
+
+    use super::*;
+
+    fn files(dir: &Path, count: usize) -> Vec<RetentionFile> {
+        (0..count)
+            .map(|index| {
+                let filename = format!("{index}.tar");
+                fs::write(dir.join(&filename), "").unwrap();
+
+                RetentionFile {
+                    filename,
+                    ..Default::default()
+                }
+            })
+            .collect()
+    }
+
+    #[test_case(None)]
+    #[test_case(NonZeroUsize::new(1))]
+    #[test_case(NonZeroUsize::new(4))]
+    #[test_case(NonZeroUsize::new(100))]
+    fn delete_should_remove_all_files(concurrency: Option<NonZeroUsize>) {
+        let dir = tempfile::tempdir().unwrap();
+        let files = files(dir.path(), 50);
+        fs::create_dir(dir.path().join("nested.d")).unwrap();
+        fs::write(dir.path().join("nested.d").join("data"), "").unwrap();
+        let files = [files, vec![
+            RetentionFile { filename: "nested.d".into(), ..Default::default() },
+            RetentionFile { filename: "missing.tar".into(), ..Default::default() },
+        ]].concat();
+        let deleter = Deleter::new(DeletionOptions { concurrency, rate_limit: None });
+
+        deleter.delete(&LocalStorage::new(dir.path().to_path_buf()), &files).unwrap();
+
+        assert_eq!(0, fs::read_dir(dir.path()).unwrap().count());
+    }
+
+    #[test]
+    fn delete_should_respect_rate_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = files(dir.path(), 5);
+        let deleter = Deleter::new(DeletionOptions {
+            concurrency: NonZeroUsize::new(5),
+            rate_limit: Some(20),
+        });
+        let start = Instant::now();
+
+        deleter.delete(&LocalStorage::new(dir.path().to_path_buf()), &files).unwrap();
+
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+
+    /// Records the size of every removed batch.
+    #[derive(Default)]
+    struct BatchStorage {
+        batch_size: usize,
+        batches: Mutex<Vec<usize>>,
+    }
+
+    impl Storage for BatchStorage {
+        fn list(&self) -> Result<Box<dyn Iterator<Item = FileEntry> + '_>> {
+            Ok(Box::new(std::iter::empty()))
+        }
+
+        fn remove(&self, name: &str) -> Result<()> {
+            self.remove_all(&[name])
+        }
+
+        fn remove_all(&self, names: &[&str]) -> Result<()> {
+            self.batches.lock().unwrap().push(names.len());
+            Ok(())
+        }
+
+        fn batch_size(&self) -> usize {
+            self.batch_size
+        }
+    }
+
+    #[test]
+    fn delete_should_remove_files_in_batches() {
+        let files = vec![RetentionFile::default(); 7];
+        let storage = BatchStorage { batch_size: 3, ..Default::default() };
+
+        Deleter::new(DeletionOptions::default()).delete(&storage, &files).unwrap();
+
+        assert_eq!(vec![3, 3, 1], storage.batches.into_inner().unwrap());
+    }
+
+    #[test]
+    fn delete_should_count_every_file_of_batch_for_rate_limit() {
+        let files = vec![RetentionFile::default(); 70];
+        let storage = BatchStorage { batch_size: 1000, ..Default::default() };
+        let deleter = Deleter::new(DeletionOptions {
+            concurrency: NonZeroUsize::new(4),
+            rate_limit: Some(50),
+        });
+        let start = Instant::now();
+
+        deleter.delete(&storage, &files).unwrap();
+
+        // The second batch may only start after the 50 files of the first batch took their second
+        assert!(start.elapsed() >= Duration::from_secs(1));
+        assert_eq!(vec![50, 20], storage.batches.into_inner().unwrap());
+    }
+
+    #[test]
+    fn zero_rate_limit_should_disable_limiter() {
+        let deleter = Deleter::new(DeletionOptions {
+            concurrency: None,
+            rate_limit: Some(0),
+        });
+
+        assert!(deleter.limiter.is_none());
+    }
+
+    #[test]
+    fn merge_should_override_set_fields() {
+        let base = DeletionOptions {
+            concurrency: NonZeroUsize::new(4),
+            rate_limit: Some(100),
+        };
+
+        let options = base.merge(&DeletionOptions {
+            concurrency: None,
+            rate_limit: Some(0),
+        });
+
+        assert_eq!(DeletionOptions {
+            concurrency: NonZeroUsize::new(4),
+            rate_limit: Some(0),
+        }, options);
+    }
+}
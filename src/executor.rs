@@ -0,0 +1,402 @@
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::config::{Config, ConfigError, DeletionOptions, RetentionPath, RetentionPolicy};
+use crate::deletion::Deleter;
+use crate::file::RetentionFile;
+use crate::listing::{read_listing, ListingFormat};
+use crate::storage::{self, MemoryStorage, Storage, StorageError};
+use crate::stream::RetentionStream;
+
+/// Errors while applying retention policies.
+#[derive(Debug, thiserror::Error)]
+pub enum ExecutionError {
+    #[error("Unable to read {path:?}: {error}")]
+    Io { path: PathBuf, error: io::Error },
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error("Retention policy failed for {}", .0.join(", "))]
+    Failed(Vec<String>),
+}
+
+type Result<T> = std::result::Result<T, ExecutionError>;
+
+/// Amount of dropped files collected before they are deleted.
+const BATCH_SIZE: usize = 1000;
+
+/// Outcome of applying the retention policy to a single path.
+#[derive(Debug, Default, Clone)]
+pub struct PathReport {
+    pub kept: usize,
+    pub dropped: usize,
+    /// Sum of the sizes of the dropped files, as far as they are known
+    pub freed_bytes: u64,
+    /// Files which don't match the file pattern
+    pub unparseable: usize,
+    /// Files which match the file pattern but contain a date which doesn't exist
+    pub invalid_dates: usize,
+}
+
+impl PathReport {
+    fn add(&mut self, other: &PathReport) {
+        self.kept += other.kept;
+        self.dropped += other.dropped;
+        self.freed_bytes += other.freed_bytes;
+        self.unparseable += other.unparseable;
+        self.invalid_dates += other.invalid_dates;
+    }
+}
+
+/// Decides which storage files are read from and whether dropped files are deleted.
+pub enum ExecutionContext {
+    /// Reads the storage of each path and deletes the dropped files
+    Default,
+    /// Reads the storage of each path and only logs which files would be kept and dropped
+    DryRun,
+    /// Streams listings through a [MemoryStorage] instead of the storage of each path and logs which files would be kept and dropped
+    Simulate {
+        /// Input files by name or path of the [RetentionPath]
+        inputs: Vec<(String, PathBuf)>,
+        input_format: ListingFormat,
+        /// Whether paths without an input are read from the filesystem
+        read_missing: bool,
+    }
+}
+
+/// Reads the simulation input from stdin instead of a file.
+pub const STDIN_INPUT: &str = "-";
+
+impl ExecutionContext {
+    /// Warns about simulation inputs which don't belong to any path.
+    pub fn check_inputs(&self, config: &Config) {
+        if let Self::Simulate { inputs, .. } = self {
+            for (selector, input) in inputs {
+                if !config.paths.iter().any(|path| path.matches(selector)) {
+                    tracing::warn!(?input, "No path matches '{selector}', input is ignored");
+                }
+            }
+        }
+    }
+
+    /// Applies the retention policies of all paths using up to `jobs` threads.
+    ///
+    /// Entries pointing to the same directory are processed one after another by the same thread.
+    /// A failing path doesn't stop the others, the error is reported once all paths are done.
+    /// Returns the sum of the reports of all paths.
+    pub fn run(&self, config: &Config, jobs: usize) -> Result<PathReport> {
+        let groups = group_by_directory(&config.paths);
+        let next_group = AtomicUsize::new(0);
+        let reports = Mutex::new(Vec::with_capacity(config.paths.len()));
+
+        thread::scope(|scope| {
+            for _ in 0..jobs.clamp(1, groups.len().max(1)) {
+                scope.spawn(|| {
+                    while let Some(group) = groups.get(next_group.fetch_add(1, Ordering::Relaxed)) {
+                        for (index, path) in group.iter().copied() {
+                            let span = tracing::info_span!("path", name = %path.name());
+                            let _enter = span.enter();
+                            let report = config.policy_for(path)
+                                .map_err(ExecutionError::from)
+                                .and_then(|policy| {
+                                    tracing::trace!(?policy);
+                                    self.process(path, policy, config.deletion_for(path))
+                                });
+                            if let Err(err) = report.as_ref() {
+                                tracing::error!("Unable to apply retention policy: {err}");
+                            }
+                            reports.lock().unwrap().push((index, report));
+                        }
+                    }
+                });
+            }
+        });
+
+        let mut reports = reports.into_inner().unwrap();
+        reports.sort_by_key(|(index, _)| *index);
+        let mut total = PathReport::default();
+        let mut failed = Vec::new();
+        for (index, report) in reports {
+            let path = &config.paths[index];
+            match report {
+                Ok(report) => {
+                    tracing::info!(name = %path.name(), kept = report.kept, dropped = report.dropped, freed_bytes = report.freed_bytes, unparseable = report.unparseable, invalid_dates = report.invalid_dates, "Summary");
+                    total.add(&report);
+                }
+                Err(_) => failed.push(path.name().to_string()),
+            }
+        }
+        tracing::info!(paths = config.paths.len(), failed = failed.len(), kept = total.kept, dropped = total.dropped, freed_bytes = total.freed_bytes, unparseable = total.unparseable, invalid_dates = total.invalid_dates, "Finished");
+
+        if failed.is_empty() {
+            Ok(total)
+        } else {
+            Err(ExecutionError::Failed(failed))
+        }
+    }
+
+    /// Applies the policy to the files of the given path, read from the storage selected by [ExecutionContext::storage].
+    pub fn process(&self, path: &RetentionPath, policy: RetentionPolicy, deletion: DeletionOptions) -> Result<PathReport> {
+        let storage = self.storage(path)?;
+
+        self.process_storage(path, storage.as_ref(), policy, deletion)
+    }
+
+    /// Applies the policy to the files of the given storage.
+    ///
+    /// Files are parsed while the storage is listed, only files which might be kept are held in memory.
+    /// Files which can't be kept anymore are dropped in batches.
+    pub fn process_storage(&self, path: &RetentionPath, storage: &dyn Storage, policy: RetentionPolicy, deletion: DeletionOptions) -> Result<PathReport> {
+        let mut report = PathReport::default();
+        let deleter = Deleter::new(deletion);
+        let mut stream = RetentionStream::new(policy);
+        let mut batch = Vec::with_capacity(BATCH_SIZE);
+
+        for entry in storage.list()? {
+            match RetentionFile::new(entry, &path.file_pattern) {
+                Ok(file) => batch.extend(stream.push(file)),
+                // Files with an impossible date look like backups, but are never deleted as their age is unknown
+                Err(err) if err.is_invalid_date() => {
+                    report.invalid_dates += 1;
+                    tracing::warn!(%err, "File has an invalid date and is kept");
+                }
+                Err(err) => {
+                    report.unparseable += 1;
+                    tracing::warn!(%err, "Unable to parse file");
+                }
+            }
+            if batch.len() >= BATCH_SIZE {
+                self.drop_files(&deleter, storage, std::mem::take(&mut batch), &mut report)?;
+            }
+        }
+        let untracked = stream.untracked();
+        let (keep, drop) = stream.finish();
+        batch.extend(drop);
+        self.drop_files(&deleter, storage, batch, &mut report)?;
+        report.kept = keep.len() + untracked;
+        self.keep_files(keep);
+
+        tracing::info!(kept = report.kept, dropped = report.dropped, freed_bytes = report.freed_bytes, unparseable = report.unparseable, invalid_dates = report.invalid_dates, "Applied retention policy");
+
+        Ok(report)
+    }
+
+    /// Opens the storage files of the path are read from.
+    ///
+    /// Simulations stream the input of the path through a [MemoryStorage], paths without an input are empty unless `read_missing` is set.
+    pub fn storage(&self, path: &RetentionPath) -> Result<Box<dyn Storage>> {
+        match self {
+            Self::Default | Self::DryRun => Ok(storage::open(&path.path)?),
+            Self::Simulate { inputs, input_format, read_missing } => {
+                match inputs.iter().find(|(selector, _)| path.matches(selector)) {
+                    Some((_, input)) if input == Path::new(STDIN_INPUT) => {
+                        Ok(Box::new(MemoryStorage::streaming(read_listing(BufReader::new(io::stdin()), *input_format, input.clone()))))
+                    }
+                    Some((_, input)) => {
+                        let file = File::open(input).map_err(|error| ExecutionError::Io { path: input.clone(), error })?;
+
+                        Ok(Box::new(MemoryStorage::streaming(read_listing(BufReader::new(file), *input_format, input.clone()))))
+                    }
+                    None if *read_missing => Ok(storage::open(&path.path)?),
+                    None => Ok(Box::new(MemoryStorage::default())),
+                }
+            }
+        }
+    }
+
+    fn drop_files(&self, deleter: &Deleter, storage: &dyn Storage, drop: Vec<RetentionFile>, report: &mut PathReport) -> Result<()> {
+        if drop.is_empty() {
+            return Ok(());
+        }
+        report.dropped += drop.len();
+        report.freed_bytes += drop.iter().filter_map(|file| file.size.or_else(|| storage.size(&file.filename))).sum::<u64>();
+        match self {
+            Self::Default => Ok(deleter.delete(storage, &drop)?),
+            Self::Simulate { .. } | Self::DryRun => {
+                let drop: Vec<_> = drop.into_iter().map(|file| file.filename).collect();
+                tracing::info!(?drop, "Dropping files");
+
+                Ok(())
+            }
+        }
+    }
+
+    fn keep_files(&self, keep: Vec<RetentionFile>) {
+        if let Self::Simulate { .. } | Self::DryRun = self {
+            let keep: Vec<_> = keep.into_iter().map(|file| file.filename).collect();
+            tracing::info!(?keep, "Keeping files");
+        }
+    }
+}
+
+/// Groups the paths by the directory they point to, keeping the index of each path.
+fn group_by_directory(paths: &[RetentionPath]) -> Vec<Vec<(usize, &RetentionPath)>> {
+    let mut groups: Vec<(PathBuf, Vec<(usize, &RetentionPath)>)> = Vec::new();
+    for (index, path) in paths.iter().enumerate() {
+        let directory = fs::canonicalize(&path.path).unwrap_or_else(|_| path.path.clone());
+        match groups.iter_mut().find(|(other, _)| *other == directory) {
+            Some((_, group)) => group.push((index, path)),
+            None => groups.push((directory, vec![(index, path)])),
+        }
+    }
+
+    groups.into_iter().map(|(_, group)| group).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use test_case::test_case;
+
+    use crate::file::FileEntry;
+
+    use super::*;
+
+    fn path(path: impl Into<PathBuf>) -> RetentionPath {
+        let pattern = "{year}-{month}-{day}".parse::<crate::config::RetentionFilePattern>().unwrap();
+
+        RetentionPath::new(path.into(), pattern.compile().unwrap())
+    }
+
+    #[test]
+    fn group_by_directory_should_group_same_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("pg")).unwrap();
+        fs::create_dir(dir.path().join("hub")).unwrap();
+        let paths = vec![
+            path(dir.path().join("pg")),
+            path(dir.path().join("hub")),
+            path(dir.path().join("hub").join("..").join("pg")),
+            path("/does/not/exist"),
+        ];
+
+        let groups = group_by_directory(&paths);
+
+        let groups: Vec<Vec<_>> = groups.into_iter().map(|group| group.into_iter().map(|(index, _)| index).collect()).collect();
+        assert_eq!(vec![vec![0, 2], vec![1], vec![3]], groups);
+    }
+
+    #[test]
+    fn run_should_process_every_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut paths = Vec::new();
+        for name in ["a", "b", "c", "d"] {
+            fs::create_dir(dir.path().join(name)).unwrap();
+            for day in 1..=9 {
+                fs::write(dir.path().join(name).join(format!("2024-05-0{day}")), "").unwrap();
+            }
+            paths.push(path(dir.path().join(name)));
+        }
+        let config = Config {
+            retention: RetentionPolicy {
+                keep_last: Some(2),
+                ..Default::default()
+            },
+            profiles: Default::default(),
+            deletion: Default::default(),
+            paths,
+        };
+
+        ExecutionContext::Default.run(&config, 3).unwrap();
+
+        for name in ["a", "b", "c", "d"] {
+            let mut files: Vec<_> = fs::read_dir(dir.path().join(name)).unwrap()
+                .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+                .collect();
+            files.sort();
+            assert_eq!(vec!["2024-05-08", "2024-05-09"], files);
+        }
+    }
+
+    #[test]
+    fn run_should_keep_files_with_invalid_dates() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["2024-05-01", "2024-05-02", "2024-02-30", "2024-13-01", "backup"] {
+            fs::write(dir.path().join(name), "").unwrap();
+        }
+        let config = Config {
+            retention: RetentionPolicy {
+                keep_last: Some(1),
+                ..Default::default()
+            },
+            profiles: Default::default(),
+            deletion: Default::default(),
+            paths: vec![path(dir.path())],
+        };
+
+        let report = ExecutionContext::Default.run(&config, 1).unwrap();
+
+        assert_eq!((1, 1, 1, 2), (report.kept, report.dropped, report.unparseable, report.invalid_dates));
+        assert!(!dir.path().join("2024-05-01").exists());
+        assert!(dir.path().join("2024-02-30").exists());
+        assert!(dir.path().join("2024-13-01").exists());
+    }
+
+    #[test]
+    fn run_should_report_size_of_dropped_files() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("2024-05-01"), "first").unwrap();
+        fs::write(dir.path().join("2024-05-02"), "second").unwrap();
+        fs::write(dir.path().join("2024-05-03"), "third").unwrap();
+        let config = Config {
+            retention: RetentionPolicy {
+                keep_last: Some(1),
+                ..Default::default()
+            },
+            profiles: Default::default(),
+            deletion: Default::default(),
+            paths: vec![path(dir.path())],
+        };
+
+        let report = ExecutionContext::Default.run(&config, 1).unwrap();
+
+        assert_eq!(11, report.freed_bytes);
+    }
+
+    #[test_case(ExecutionContext::Default, vec!["2024-05-03", "backup"])]
+    #[test_case(ExecutionContext::DryRun, vec!["2024-05-01", "2024-05-02", "2024-05-03", "backup"])]
+    fn process_storage_should_only_delete_in_default_mode(context: ExecutionContext, expected: Vec<&str>) {
+        let storage: MemoryStorage = ["2024-05-01", "2024-05-02", "2024-05-03", "backup"].into_iter()
+            .map(|name| FileEntry::new(name.to_string()))
+            .collect();
+        let policy = RetentionPolicy {
+            keep_last: Some(1),
+            ..Default::default()
+        };
+
+        let report = context.process_storage(&path("/var/backups/pg"), &storage, policy, Default::default()).unwrap();
+
+        assert_eq!((1, 2, 1), (report.kept, report.dropped, report.unparseable));
+        assert_eq!(expected, storage.names());
+    }
+
+    #[test]
+    fn run_should_continue_after_failing_path() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("2024-05-01"), "").unwrap();
+        fs::write(dir.path().join("2024-05-02"), "").unwrap();
+        let config = Config {
+            retention: RetentionPolicy {
+                keep_last: Some(1),
+                ..Default::default()
+            },
+            profiles: Default::default(),
+            deletion: Default::default(),
+            paths: vec![path("/does/not/exist"), path(dir.path())],
+        };
+
+        let result = ExecutionContext::Default.run(&config, 1);
+
+        assert!(result.is_err());
+        assert!(!dir.path().join("2024-05-01").exists());
+        assert!(dir.path().join("2024-05-02").exists());
+    }
+}
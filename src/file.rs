@@ -1,101 +1,283 @@
+use std::num::ParseIntError;
 use std::str::FromStr;
 
 use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
-use color_eyre::eyre::Context;
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 
 use crate::config::RetentionFilePattern;
-use crate::Result;
 
+/// Placeholders which contribute to the date of a file.
+const DATE_PLACEHOLDERS: &[&str] = &["year", "month", "month_abr", "month_abbr", "day", "hour", "minutes", "seconds"];
+/// Placeholders which are matched but not used for the date.
+const OTHER_PLACEHOLDERS: &[&str] = &["name", "TZ"];
+
+/// Errors while compiling a file pattern or parsing the date of a filename.
+#[derive(Debug, thiserror::Error)]
+pub enum PatternError {
+    #[error("Invalid file pattern '{pattern}': {}", issues.iter().map(ToString::to_string).collect::<Vec<_>>().join(", "))]
+    InvalidPattern { pattern: String, issues: Vec<PatternIssue> },
+    #[error("File pattern '{pattern}' does not compile to a valid regex: {error}")]
+    InvalidRegex { pattern: String, error: regex::Error },
+    /// The file is not a backup
+    #[error("Filename '{filename}' doesn't match the file pattern")]
+    NoMatch { filename: String },
+    /// The file looks like a backup but its date doesn't exist
+    #[error("Filename '{filename}' contains the invalid date {year:04}-{month:02}-{day:02}")]
+    InvalidDate {
+        filename: String,
+        year: i32,
+        month: u32,
+        day: u32,
+    },
+    /// The file looks like a backup but its time doesn't exist
+    #[error("Filename '{filename}' contains the invalid time {hour:02}:{minutes:02}:{seconds:02}")]
+    InvalidTime {
+        filename: String,
+        hour: u32,
+        minutes: u32,
+        seconds: u32,
+    },
+    #[error("Filename '{filename}' contains the unknown month '{month}'")]
+    UnknownMonth { filename: String, month: String },
+    #[error("Invalid {placeholder} '{value}' in filename '{filename}': {error}")]
+    InvalidNumber {
+        filename: String,
+        placeholder: &'static str,
+        value: String,
+        error: ParseIntError,
+    },
+}
+
+impl PatternError {
+    /// Whether the filename matches the pattern, but the date or time it contains doesn't exist.
+    pub fn is_invalid_date(&self) -> bool {
+        matches!(self, Self::InvalidDate { .. } | Self::InvalidTime { .. } | Self::UnknownMonth { .. })
+    }
+}
+
+type Result<T> = std::result::Result<T, PatternError>;
+
+/// Directory entry together with the metadata known about it.
+///
+/// The metadata is only reported, e.g. the size of dropped files as `freed_bytes`. Retention only uses the date in the name.
 #[derive(Debug, Clone, Eq, PartialEq)]
+pub struct FileEntry {
+    pub name: String,
+    /// Size in bytes
+    pub size: Option<u64>,
+    /// Last modification time
+    pub modified: Option<DateTime<Utc>>,
+}
+
+impl FileEntry {
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            size: None,
+            modified: None,
+        }
+    }
+}
+
+/// File with the date parsed from its name, the input of [RetentionPolicy::retain](crate::config::RetentionPolicy::retain).
+#[derive(Default, Debug, Clone, Eq, PartialEq)]
 pub struct RetentionFile {
     pub filename: String,
+    /// Date parsed from the filename
     pub date: DateTime<Utc>,
+    /// Size in bytes, reported as `freed_bytes` when the file is dropped
+    pub size: Option<u64>,
+    /// Last modification time, not used to decide whether the file is kept
+    pub modified: Option<DateTime<Utc>>,
 }
 
 impl RetentionFile {
-    pub fn new(filename: String, pattern: &RetentionFilePattern) -> Result<Self> {
+    /// Parses the date of the entry using the given pattern.
+    pub fn new(entry: FileEntry, pattern: &CompiledFilePattern) -> Result<Self> {
         Ok(Self {
-            date: pattern.parse(&filename)?,
-            filename,
+            date: pattern.parse(&entry.name)?,
+            filename: entry.name,
+            size: entry.size,
+            modified: entry.modified,
         })
     }
 }
 
-impl RetentionFilePattern {
-    pub fn parse(&self, filename: &str) -> Result<DateTime<Utc>> {
-        let regex = self.build_regex()?;
-        let captures = regex.captures(filename).ok_or_else(|| color_eyre::eyre::eyre!("Filename '{filename}' doesn't match file pattern."))
-            .context(format!("Applying regex {regex:?} on filename"))?;
-        let year = if let Some(year) = captures.name("year") {
-            let year = i32::from_str(year.as_str())?;
+/// File pattern with its regex compiled once when the config is loaded.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(try_from = "RetentionFilePattern", into = "RetentionFilePattern")]
+pub struct CompiledFilePattern {
+    pattern: RetentionFilePattern,
+    regex: Regex,
+}
 
-            Some(year)
-        } else {
-            None
-        };
-        let month = if let Some(month) = captures.name("month") {
-            let month = u32::from_str(month.as_str())?;
+impl TryFrom<RetentionFilePattern> for CompiledFilePattern {
+    type Error = PatternError;
 
-            Some(month)
-        } else if let Some(month_abr) = captures.name("month_abbr") {
-            match month_abr.as_str().to_lowercase().as_str() {
-                "jan" => Some(1),
-                "feb" => Some(2),
-                "mar" => Some(3),
-                "apr" => Some(4),
-                "may" => Some(5),
-                "jun" => Some(6),
-                "jul" => Some(7),
-                "aug" => Some(8),
-                "sep" => Some(9),
-                "oct" => Some(10),
-                "nov" => Some(11),
-                "dec" => Some(12),
-                _ => None,
-            }
-        } else {
-            None
-        };
-        let day = if let Some(day) = captures.name("day") {
-            let day = u32::from_str(day.as_str())?;
+    fn try_from(pattern: RetentionFilePattern) -> Result<Self> {
+        pattern.compile()
+    }
+}
 
-            Some(day)
-        } else {
-            None
-        };
-        let hour = if let Some(hour) = captures.name("hour") {
-            let hour = u32::from_str(hour.as_str())?;
+impl From<CompiledFilePattern> for RetentionFilePattern {
+    fn from(pattern: CompiledFilePattern) -> Self {
+        pattern.pattern
+    }
+}
 
-            Some(hour)
-        } else {
-            None
-        };
-        let minutes = if let Some(minutes) = captures.name("minutes") {
-            let minutes = u32::from_str(minutes.as_str())?;
+impl CompiledFilePattern {
+    /// The pattern this regex was compiled from.
+    pub fn pattern(&self) -> &RetentionFilePattern {
+        &self.pattern
+    }
 
-            Some(minutes)
-        } else {
-            None
+    /// Extracts the date from the given filename.
+    ///
+    /// Date parts missing in the pattern default to the first possible value, the year to 2022.
+    pub fn parse(&self, filename: &str) -> Result<DateTime<Utc>> {
+        let captures = self.regex.captures(filename).ok_or_else(|| PatternError::NoMatch { filename: filename.to_string() })?;
+        let number = |placeholder: &'static str| -> Result<Option<u32>> {
+            captures.name(placeholder)
+                .map(|value| u32::from_str(value.as_str()).map_err(|error| PatternError::InvalidNumber {
+                    filename: filename.to_string(),
+                    placeholder,
+                    value: value.as_str().to_string(),
+                    error,
+                }))
+                .transpose()
         };
-        let seconds = if let Some(seconds) = captures.name("seconds") {
-            let seconds = u32::from_str(seconds.as_str())?;
+        let year = number("year")?.map(|year| year as i32);
+        let month = if let Some(month) = number("month")? {
+            Some(month)
+        } else if let Some(month_abr) = captures.name("month_abbr") {
+            let month = match month_abr.as_str().to_lowercase().as_str() {
+                "jan" => 1,
+                "feb" => 2,
+                "mar" => 3,
+                "apr" => 4,
+                "may" => 5,
+                "jun" => 6,
+                "jul" => 7,
+                "aug" => 8,
+                "sep" => 9,
+                "oct" => 10,
+                "nov" => 11,
+                "dec" => 12,
+                _ => return Err(PatternError::UnknownMonth {
+                    filename: filename.to_string(),
+                    month: month_abr.as_str().to_string(),
+                }),
+            };
 
-            Some(seconds)
+            Some(month)
         } else {
             None
         };
+        let day = number("day")?;
+        let hour = number("hour")?;
+        let minutes = number("minutes")?;
+        let seconds = number("seconds")?;
         // let offset = if let Some(offset) = captures.name("timezone") {
         //     let timezone =
         // }
-        let date = NaiveDate::from_ymd_opt(year.unwrap_or(2022), month.unwrap_or(1), day.unwrap_or(1)).unwrap();
-        let time = NaiveTime::from_hms_opt(hour.unwrap_or_default(), minutes.unwrap_or_default(), seconds.unwrap_or_default()).unwrap();
+        let (year, month, day) = (year.unwrap_or(2022), month.unwrap_or(1), day.unwrap_or(1));
+        let date = NaiveDate::from_ymd_opt(year, month, day).ok_or_else(|| PatternError::InvalidDate {
+            filename: filename.to_string(),
+            year,
+            month,
+            day,
+        })?;
+        let (hour, minutes, seconds) = (hour.unwrap_or_default(), minutes.unwrap_or_default(), seconds.unwrap_or_default());
+        let time = NaiveTime::from_hms_opt(hour, minutes, seconds).ok_or_else(|| PatternError::InvalidTime {
+            filename: filename.to_string(),
+            hour,
+            minutes,
+            seconds,
+        })?;
         let datetime = DateTime::from_naive_utc_and_offset(NaiveDateTime::new(date, time), *Utc::now().offset());
 
         Ok(datetime)
     }
+}
 
-    fn build_regex(&self) -> Result<Regex> {
+impl RetentionFilePattern {
+    /// Compiles the pattern into a regex, failing for unknown or duplicate placeholders.
+    pub fn compile(&self) -> Result<CompiledFilePattern> {
+        let issues = self.validate()
+            .into_iter()
+            .filter(|issue| !matches!(issue, PatternIssue::NoDatePlaceholder | PatternIssue::InvalidRegex(_)))
+            .collect::<Vec<_>>();
+        if !issues.is_empty() {
+            return Err(PatternError::InvalidPattern { pattern: self.0.clone(), issues });
+        }
+        let regex = self.build_regex()
+            .map_err(|error| PatternError::InvalidRegex { pattern: self.0.clone(), error })?;
+
+        Ok(CompiledFilePattern {
+            pattern: self.clone(),
+            regex,
+        })
+    }
+
+    /// Extracts the date from a single filename.
+    ///
+    /// Compiles the pattern on every call, use [RetentionFilePattern::compile] when parsing many filenames.
+    pub fn parse(&self, filename: &str) -> Result<DateTime<Utc>> {
+        self.compile()?.parse(filename)
+    }
+
+    /// Returns all `{placeholder}` names in the order they appear in the pattern.
+    ///
+    /// Regex quantifiers like `{4}` or `{1,2}` are not considered placeholders.
+    pub fn placeholders(&self) -> Vec<&str> {
+        let mut placeholders = Vec::new();
+        let mut rest = self.0.as_str();
+        while let Some(start) = rest.find('{') {
+            rest = &rest[start + 1..];
+            let Some(end) = rest.find('}') else {
+                break;
+            };
+            let name = &rest[..end];
+            if name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                placeholders.push(name);
+                rest = &rest[end + 1..];
+            }
+        }
+
+        placeholders
+    }
+
+    /// Checks the pattern for mistakes which would only surface while parsing file names.
+    pub fn validate(&self) -> Vec<PatternIssue> {
+        let mut issues = Vec::new();
+        let placeholders = self.placeholders();
+
+        let mut seen = Vec::new();
+        for placeholder in placeholders.iter().copied() {
+            if !DATE_PLACEHOLDERS.contains(&placeholder) && !OTHER_PLACEHOLDERS.contains(&placeholder) {
+                issues.push(PatternIssue::UnknownPlaceholder(placeholder.to_string()));
+                continue;
+            }
+            // {month_abr} is only an alias for {month_abbr}
+            let group = if placeholder == "month_abr" { "month_abbr" } else { placeholder };
+            if seen.contains(&group) {
+                issues.push(PatternIssue::DuplicatePlaceholder(placeholder.to_string()));
+            } else {
+                seen.push(group);
+            }
+        }
+        if !placeholders.iter().any(|placeholder| DATE_PLACEHOLDERS.contains(placeholder)) {
+            issues.push(PatternIssue::NoDatePlaceholder);
+        }
+        if let Err(err) = self.build_regex() {
+            issues.push(PatternIssue::InvalidRegex(err.to_string()));
+        }
+
+        issues
+    }
+
+    fn build_regex(&self) -> std::result::Result<Regex, regex::Error> {
         let regex_str = self.0
             .replace("{name}", "(?P<name>.+)")
             .replace("{year}", "(?P<year>\\d{4})")
@@ -114,12 +296,33 @@ impl RetentionFilePattern {
     }
 }
 
+/// Mistake in a file pattern found by [RetentionFilePattern::validate].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatternIssue {
+    UnknownPlaceholder(String),
+    DuplicatePlaceholder(String),
+    NoDatePlaceholder,
+    InvalidRegex(String),
+}
+
+impl std::fmt::Display for PatternIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownPlaceholder(placeholder) => write!(f, "Unknown placeholder {{{placeholder}}}"),
+            Self::DuplicatePlaceholder(placeholder) => write!(f, "Placeholder {{{placeholder}}} is used more than once"),
+            Self::NoDatePlaceholder => write!(f, "Pattern contains no date placeholder, all files would share the same date"),
+            Self::InvalidRegex(err) => write!(f, "Pattern does not compile to a valid regex: {err}"),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use chrono::{Datelike, Timelike};
     use test_case::test_case;
 
     use crate::config::RetentionFilePattern;
+    use crate::file::{PatternError, PatternIssue};
 
     #[test_case("2022", 2022)]
     #[test_case("2020", 2020)]
@@ -223,4 +426,113 @@ mod tests {
 
         assert_eq!(seconds, date_time.second())
     }
+
+    #[test_case("{name}_{year}-{month}-{day}T{hour}:{minutes}{TZ}.bck", vec!["name", "year", "month", "day", "hour", "minutes", "TZ"])]
+    #[test_case("backup-\\d{4}-{year}", vec!["year"])]
+    #[test_case("{year}-\\d{1,2}", vec!["year"])]
+    fn placeholders(pattern: &str, expected: Vec<&str>) {
+        let file_pattern = RetentionFilePattern(pattern.to_string());
+
+        let placeholders = file_pattern.placeholders();
+
+        assert_eq!(expected, placeholders);
+    }
+
+    #[test_case("{year}-{month}-{day}", vec![])]
+    #[test_case("{name}_{year} {month_abbr}", vec![])]
+    #[test_case("{year}-{mnth}", vec![PatternIssue::UnknownPlaceholder("mnth".into())])]
+    #[test_case("{year}-{day}-{day}", vec![PatternIssue::DuplicatePlaceholder("day".into())])]
+    #[test_case("{month_abr}-{month_abbr}", vec![PatternIssue::DuplicatePlaceholder("month_abbr".into())])]
+    #[test_case("{name}.tar", vec![PatternIssue::NoDatePlaceholder])]
+    fn validate(pattern: &str, expected: Vec<PatternIssue>) {
+        let file_pattern = RetentionFilePattern(pattern.to_string());
+
+        let issues = file_pattern.validate();
+
+        let issues: Vec<_> = issues.into_iter().filter(|issue| !matches!(issue, PatternIssue::InvalidRegex(_))).collect();
+        assert_eq!(expected, issues);
+    }
+
+    #[test_case("{year}-(")]
+    #[test_case("{year}-{unknown}")]
+    fn validate_invalid_regex(pattern: &str) {
+        let file_pattern = RetentionFilePattern(pattern.to_string());
+
+        let issues = file_pattern.validate();
+
+        assert!(issues.iter().any(|issue| matches!(issue, PatternIssue::InvalidRegex(_))));
+    }
+
+    #[test_case("{year}-{month}-{day}.tar", "README.md")]
+    #[test_case("{year}-{month}-{day}.tar", "backup.tar")]
+    #[test_case("{name}-{year}", "backup")]
+    fn parse_should_fail_with_no_match(pattern: &str, filename: &str) {
+        let file_pattern = RetentionFilePattern(pattern.to_string());
+
+        let result = file_pattern.parse(filename);
+
+        assert!(matches!(result, Err(PatternError::NoMatch { .. })));
+    }
+
+    #[test_case("{year}-{month}-{day}.tar", "2024-02-30.tar", (2024, 2, 30))]
+    #[test_case("{year}-{month}-{day}.tar", "2023-02-29.tar", (2023, 2, 29))]
+    #[test_case("{year}-{month}-{day}.tar", "2024-13-01.tar", (2024, 13, 1))]
+    #[test_case("{year}-{month}.tar", "2024-00.tar", (2024, 0, 1))]
+    #[test_case("{year}-{month}-{day}.tar", "2024-04-31.tar", (2024, 4, 31))]
+    fn parse_should_fail_with_invalid_date(pattern: &str, filename: &str, expected: (i32, u32, u32)) {
+        let file_pattern = RetentionFilePattern(pattern.to_string());
+
+        let result = file_pattern.parse(filename);
+
+        let Err(PatternError::InvalidDate { year, month, day, .. }) = result else {
+            panic!("Expected invalid date, got {result:?}");
+        };
+        assert_eq!(expected, (year, month, day));
+    }
+
+    #[test_case("{year}-{month}-{day}T{hour}:{minutes}", "2024-05-01T25:00", (25, 0, 0))]
+    #[test_case("{year}-{month}-{day}T{hour}:{minutes}", "2024-05-01T24:00", (24, 0, 0))]
+    #[test_case("{year}-{month}-{day}T{hour}:{minutes}", "2024-05-01T12:60", (12, 60, 0))]
+    #[test_case("{hour}-{minutes}-{seconds}", "23-59-60", (23, 59, 60))]
+    #[test_case("{hour}-{minutes}-{seconds}", "99-99-99", (99, 99, 99))]
+    fn parse_should_fail_with_invalid_time(pattern: &str, filename: &str, expected: (u32, u32, u32)) {
+        let file_pattern = RetentionFilePattern(pattern.to_string());
+
+        let result = file_pattern.parse(filename);
+
+        let Err(PatternError::InvalidTime { hour, minutes, seconds, .. }) = result else {
+            panic!("Expected invalid time, got {result:?}");
+        };
+        assert_eq!(expected, (hour, minutes, seconds));
+    }
+
+    #[test_case("{year}-{month}-{day}T{hour}:{minutes}", "2024-02-30T25:00")]
+    #[test_case("{year}-{month}-{day}T{hour}", "2024-00-00T00")]
+    #[test_case("{year} {month_abbr} {day}", "2024 Feb 31")]
+    fn parse_should_prefer_invalid_date(pattern: &str, filename: &str) {
+        let file_pattern = RetentionFilePattern(pattern.to_string());
+
+        let result = file_pattern.parse(filename);
+
+        assert!(matches!(result, Err(PatternError::InvalidDate { .. })));
+    }
+
+    #[test_case("2024 Foo 01")]
+    #[test_case("2024 Mai 01")]
+    fn parse_should_fail_with_unknown_month(filename: &str) {
+        let file_pattern = RetentionFilePattern("{year} {month_abbr} {day}".to_string());
+
+        let result = file_pattern.parse(filename);
+
+        assert!(matches!(result, Err(PatternError::UnknownMonth { .. })));
+    }
+
+    #[test]
+    fn compile_should_fail_with_invalid_regex() {
+        let file_pattern = RetentionFilePattern("{year}-(".to_string());
+
+        let result = file_pattern.compile();
+
+        assert!(matches!(result, Err(PatternError::InvalidRegex { .. })));
+    }
 }
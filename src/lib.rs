@@ -0,0 +1,37 @@
+//! Deletes old backups based on a retention policy inspired by proxmox.
+//!
+//! The date of each file is parsed from its name using a [RetentionFilePattern](config::RetentionFilePattern),
+//! the [RetentionPolicy](config::RetentionPolicy) then decides which files are kept.
+//!
+//! ```
+//! use file_retention_policy::config::{RetentionFilePattern, RetentionPolicy};
+//! use file_retention_policy::file::{FileEntry, RetentionFile};
+//!
+//! let pattern = RetentionFilePattern("{year}-{month}-{day}.tar".to_string()).compile()?;
+//! let files = ["2024-05-03.tar", "2024-05-02.tar", "2024-05-01.tar"]
+//!     .into_iter()
+//!     .map(|name| RetentionFile::new(FileEntry::new(name.to_string()), &pattern))
+//!     .collect::<Result<Vec<_>, _>>()?;
+//! let policy = RetentionPolicy {
+//!     keep_last: Some(2),
+//!     ..Default::default()
+//! };
+//!
+//! let (keep, drop) = policy.retain(files);
+//!
+//! assert_eq!(2, keep.len());
+//! assert_eq!("2024-05-01.tar", drop[0].filename);
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! [Config](config::Config) loads the paths and policies of the CLI,
+//! [ExecutionContext](executor::ExecutionContext) applies them to the directories.
+
+pub mod config;
+pub mod deletion;
+pub mod executor;
+pub mod file;
+pub mod listing;
+pub mod policy;
+pub mod storage;
+pub mod stream;
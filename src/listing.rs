@@ -0,0 +1,303 @@
+use std::io::BufRead;
+use std::num::{ParseFloatError, ParseIntError};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+use crate::file::FileEntry;
+
+/// Errors while parsing a listing.
+#[derive(Debug, thiserror::Error)]
+pub enum ListingError {
+    #[error("Unknown input format '{0}', expected one of auto, lines, ls, find or json")]
+    UnknownFormat(String),
+    #[error("Expected 9 columns")]
+    MissingColumns,
+    #[error("Missing {0}")]
+    MissingField(&'static str),
+    #[error("Invalid size: {0}")]
+    InvalidSize(ParseIntError),
+    #[error("Invalid modification time, use --time-style=full-iso: {0}")]
+    InvalidTime(chrono::ParseError),
+    #[error("Invalid modification time: {0}")]
+    InvalidTimestamp(ParseFloatError),
+    #[error("Modification time {0} is out of range")]
+    TimestampOutOfRange(f64),
+    #[error("Invalid JSON: {0}")]
+    Json(serde_json::Error),
+}
+
+type Result<T> = std::result::Result<T, ListingError>;
+
+/// Format of a directory listing used as simulation input.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ListingFormat {
+    /// Detect the format based on the first line
+    #[default]
+    Auto,
+    /// One filename per line
+    Lines,
+    /// Output of `ls -l --time-style=full-iso`
+    Ls,
+    /// Output of `find -printf '%f,%s,%T@\n'`
+    Find,
+    /// JSON lines with `name`, `size` and `mtime`
+    Json,
+}
+
+impl FromStr for ListingFormat {
+    type Err = ListingError;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "auto" => Ok(Self::Auto),
+            "lines" => Ok(Self::Lines),
+            "ls" => Ok(Self::Ls),
+            "find" => Ok(Self::Find),
+            "json" => Ok(Self::Json),
+            _ => Err(ListingError::UnknownFormat(value.to_string())),
+        }
+    }
+}
+
+impl ListingFormat {
+    /// Guesses the format of a listing based on a single line.
+    fn detect(line: &str) -> Self {
+        let line = line.trim_start();
+        if line.starts_with('{') {
+            return Self::Json;
+        }
+        let mut mode = line.chars();
+        let is_file_type = mode.next().is_some_and(|c| "-bcdlps".contains(c));
+        if is_file_type && mode.take(9).filter(|c| "-rwxsStT".contains(*c)).count() == 9 {
+            return Self::Ls;
+        }
+        if parse_find(line).is_ok() {
+            return Self::Find;
+        }
+
+        Self::Lines
+    }
+
+    /// Parses a single line of the listing. Returns `None` for lines which don't describe a file.
+    fn parse_line(&self, line: &str) -> Result<Option<FileEntry>> {
+        if line.trim().is_empty() {
+            return Ok(None);
+        }
+        match self {
+            Self::Auto => Self::detect(line).parse_line(line),
+            Self::Lines => Ok(Some(FileEntry::new(line.to_string()))),
+            Self::Ls => parse_ls(line),
+            Self::Find => parse_find(line).map(Some),
+            Self::Json => parse_json(line).map(Some),
+        }
+    }
+}
+
+/// Reads a listing from `reader` line by line, skipping lines which can't be parsed.
+pub fn read_listing(reader: impl BufRead, format: ListingFormat, input: PathBuf) -> impl Iterator<Item = FileEntry> {
+    let mut format = format;
+    reader.lines()
+        .enumerate()
+        .filter_map(move |(index, line)| {
+            let line = match line {
+                Ok(line) => line,
+                Err(err) => {
+                    tracing::warn!("Error while reading line from input file {input:?}: {err:?}");
+                    return None;
+                }
+            };
+            if format == ListingFormat::Auto {
+                // The first line of ls is the total size which would be detected as a plain filename
+                if line.trim().is_empty() || line.starts_with("total ") {
+                    return None;
+                }
+                format = ListingFormat::detect(&line);
+                tracing::debug!(?format, ?input, "Detected input format");
+            }
+            match format.parse_line(&line) {
+                Ok(entry) => entry,
+                Err(err) => {
+                    tracing::warn!("Unable to parse line {} of input file {input:?}: {err}", index + 1);
+                    None
+                }
+            }
+        })
+}
+
+/// Parses `-rw-r--r-- 1 user group 1234 2024-05-01 12:34:56.123456789 +0200 name`.
+fn parse_ls(line: &str) -> Result<Option<FileEntry>> {
+    if line.starts_with("total ") {
+        return Ok(None);
+    }
+    let mut rest = line;
+    let mut fields = Vec::with_capacity(8);
+    for _ in 0..8 {
+        let trimmed = rest.trim_start();
+        let end = trimmed.find(char::is_whitespace)
+            .ok_or(ListingError::MissingColumns)?;
+        fields.push(&trimmed[..end]);
+        rest = &trimmed[end..];
+    }
+    let name = rest.trim_start();
+    // Symlinks are listed as `name -> target`
+    let name = if fields[0].starts_with('l') {
+        name.split_once(" -> ").map(|(name, _)| name).unwrap_or(name)
+    } else {
+        name
+    };
+    if name.is_empty() {
+        return Err(ListingError::MissingField("filename"));
+    }
+    let size = u64::from_str(fields[4]).map_err(ListingError::InvalidSize)?;
+    let modified = DateTime::parse_from_str(&format!("{} {} {}", fields[5], fields[6], fields[7]), "%Y-%m-%d %H:%M:%S%.f %z")
+        .map_err(ListingError::InvalidTime)?;
+
+    Ok(Some(FileEntry {
+        name: name.to_string(),
+        size: Some(size),
+        modified: Some(modified.with_timezone(&Utc)),
+    }))
+}
+
+/// Parses `name,size,mtime` with the modification time as unix timestamp, the filename may contain commas.
+fn parse_find(line: &str) -> Result<FileEntry> {
+    let mut fields = line.rsplitn(3, ',');
+    let modified = fields.next().ok_or(ListingError::MissingField("modification time"))?;
+    let size = fields.next().ok_or(ListingError::MissingField("size"))?;
+    let name = fields.next().ok_or(ListingError::MissingField("filename"))?;
+
+    let size = u64::from_str(size).map_err(ListingError::InvalidSize)?;
+    let modified = f64::from_str(modified).map_err(ListingError::InvalidTimestamp)?;
+    let modified = DateTime::from_timestamp(modified.trunc() as i64, (modified.fract() * 1e9) as u32)
+        .ok_or(ListingError::TimestampOutOfRange(modified))?;
+
+    Ok(FileEntry {
+        name: name.to_string(),
+        size: Some(size),
+        modified: Some(modified),
+    })
+}
+
+#[derive(Deserialize)]
+struct JsonEntry {
+    name: String,
+    size: Option<u64>,
+    mtime: Option<JsonTimestamp>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonTimestamp {
+    Unix(f64),
+    Rfc3339(DateTime<Utc>),
+}
+
+/// Parses `{"name": "...", "size": 1234, "mtime": "2024-05-01T12:00:00Z"}`, `mtime` can also be a unix timestamp.
+fn parse_json(line: &str) -> Result<FileEntry> {
+    let entry: JsonEntry = serde_json::from_str(line).map_err(ListingError::Json)?;
+    let modified = match entry.mtime {
+        Some(JsonTimestamp::Unix(timestamp)) => Some(DateTime::from_timestamp(timestamp.trunc() as i64, (timestamp.fract() * 1e9) as u32)
+            .ok_or(ListingError::TimestampOutOfRange(timestamp))?),
+        Some(JsonTimestamp::Rfc3339(modified)) => Some(modified),
+        None => None,
+    };
+
+    Ok(FileEntry {
+        name: entry.name,
+        size: entry.size,
+        modified,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use test_case::test_case;
+
+    use super::*;
+
+    #[test_case("2022-01-01.tar", ListingFormat::Lines)]
+    #[test_case("2019 Apr 10 16-03-42", ListingFormat::Lines)]
+    #[test_case("-rw-r--r-- 1 root root 1234 2024-05-01 12:34:56.000000000 +0200 2024-05-01.tar", ListingFormat::Ls)]
+    #[test_case("drwxr-xr-x. 2 root root 4096 2024-05-01 12:34:56.000000000 +0200 2024-05-01", ListingFormat::Ls)]
+    #[test_case("2024-05-01.tar,1234,1714559696.0000000000", ListingFormat::Find)]
+    #[test_case(r#"{"name": "2024-05-01.tar", "size": 1234}"#, ListingFormat::Json)]
+    fn detect_format(line: &str, expected: ListingFormat) {
+        assert_eq!(expected, ListingFormat::detect(line));
+    }
+
+    #[test_case("-rw-r--r-- 1 root root 1234 2024-05-01 12:34:56.000000000 +0200 2024-05-01.tar", "2024-05-01.tar")]
+    #[test_case("-rw-r--r--  12 backup  backup     1234 2024-05-01 12:34:56.000000000 +0200 2019 Apr 10 16-03-42", "2019 Apr 10 16-03-42")]
+    #[test_case("lrwxrwxrwx 1 root root 1234 2024-05-01 12:34:56.000000000 +0200 latest.tar -> 2024-05-01.tar", "latest.tar")]
+    fn parse_ls_line(line: &str, name: &str) {
+        let entry = ListingFormat::Ls.parse_line(line).unwrap().unwrap();
+
+        assert_eq!(FileEntry {
+            name: name.to_string(),
+            size: Some(1234),
+            modified: Some(Utc.with_ymd_and_hms(2024, 5, 1, 10, 34, 56).unwrap()),
+        }, entry);
+    }
+
+    #[test]
+    fn parse_ls_should_skip_total() {
+        let entry = ListingFormat::Ls.parse_line("total 123").unwrap();
+
+        assert_eq!(None, entry);
+    }
+
+    #[test_case("-rw-r--r-- 1 root root 1234 May 1 12:34 2024-05-01.tar")]
+    #[test_case("-rw-r--r-- 1 root root")]
+    fn parse_ls_should_reject_invalid_lines(line: &str) {
+        let result = ListingFormat::Ls.parse_line(line);
+
+        assert!(result.is_err());
+    }
+
+    #[test_case("2024-05-01.tar,1234,1714559696.0000000000", "2024-05-01.tar")]
+    #[test_case("backup,2024-05-01.tar,1234,1714559696", "backup,2024-05-01.tar")]
+    fn parse_find_line(line: &str, name: &str) {
+        let entry = ListingFormat::Find.parse_line(line).unwrap().unwrap();
+
+        assert_eq!(FileEntry {
+            name: name.to_string(),
+            size: Some(1234),
+            modified: Some(Utc.with_ymd_and_hms(2024, 5, 1, 10, 34, 56).unwrap()),
+        }, entry);
+    }
+
+    #[test_case(r#"{"name": "2024-05-01.tar", "size": 1234, "mtime": "2024-05-01T12:34:56+02:00"}"#)]
+    #[test_case(r#"{"name": "2024-05-01.tar", "size": 1234, "mtime": 1714559696}"#)]
+    fn parse_json_line(line: &str) {
+        let entry = ListingFormat::Json.parse_line(line).unwrap().unwrap();
+
+        assert_eq!(FileEntry {
+            name: "2024-05-01.tar".to_string(),
+            size: Some(1234),
+            modified: Some(Utc.with_ymd_and_hms(2024, 5, 1, 10, 34, 56).unwrap()),
+        }, entry);
+    }
+
+    #[test]
+    fn read_listing_should_detect_ls_after_total() {
+        let input = "total 8\n-rw-r--r-- 1 root root 1234 2024-05-01 12:34:56.000000000 +0200 2024-05-01.tar\n-rw-r--r-- 1 root root 1234 2024-05-02 12:34:56.000000000 +0200 2024-05-02.tar\n";
+
+        let entries = read_listing(input.as_bytes(), ListingFormat::Auto, PathBuf::from("-"));
+
+        let names: Vec<_> = entries.into_iter().map(|entry| entry.name).collect();
+        assert_eq!(vec!["2024-05-01.tar", "2024-05-02.tar"], names);
+    }
+
+    #[test]
+    fn read_listing_should_skip_invalid_lines() {
+        let input = "{\"name\": \"2024-05-01.tar\"}\nnot json\n{\"name\": \"2024-05-02.tar\"}\n";
+
+        let entries = read_listing(input.as_bytes(), ListingFormat::Json, PathBuf::from("-"));
+
+        let names: Vec<_> = entries.into_iter().map(|entry| entry.name).collect();
+        assert_eq!(vec!["2024-05-01.tar", "2024-05-02.tar"], names);
+    }
+}
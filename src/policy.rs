@@ -1,14 +1,141 @@
 use std::collections::HashSet;
 use std::hash::Hash;
 
-use chrono::Datelike;
+use chrono::{DateTime, Datelike, Utc};
 
 use crate::config::RetentionPolicy;
 use crate::file::RetentionFile;
 
+/// Anything which can be pruned by a [RetentionPolicy], e.g. files, database rows or image tags.
+///
+/// ```
+/// use chrono::{DateTime, TimeZone, Utc};
+/// use file_retention_policy::config::RetentionPolicy;
+/// use file_retention_policy::policy::Timestamped;
+///
+/// struct Snapshot {
+///     id: u32,
+///     created: DateTime<Utc>,
+/// }
+///
+/// impl Timestamped for Snapshot {
+///     fn timestamp(&self) -> DateTime<Utc> {
+///         self.created
+///     }
+/// }
+///
+/// let snapshots = (1..=3).rev()
+///     .map(|day| Snapshot { id: day, created: Utc.with_ymd_and_hms(2024, 5, day, 0, 0, 0).unwrap() })
+///     .collect();
+/// let policy = RetentionPolicy { keep_last: Some(1), ..Default::default() };
+///
+/// let (keep, drop) = policy.retain(snapshots);
+///
+/// assert_eq!(vec![3], keep.iter().map(|snapshot| snapshot.id).collect::<Vec<_>>());
+/// assert_eq!(2, drop.len());
+/// ```
+pub trait Timestamped {
+    /// Date used to decide whether the item is kept.
+    fn timestamp(&self) -> DateTime<Utc>;
+}
+
+impl Timestamped for RetentionFile {
+    fn timestamp(&self) -> DateTime<Utc> {
+        self.date
+    }
+}
+
+impl Timestamped for DateTime<Utc> {
+    fn timestamp(&self) -> DateTime<Utc> {
+        *self
+    }
+}
+
+/// Time span in which only the latest file is kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+impl Period {
+    /// Identifier of the period the date falls into. Later periods have higher identifiers.
+    pub fn bucket(&self, date: &DateTime<Utc>) -> i64 {
+        match self {
+            Self::Daily => date.num_days_from_ce() as i64,
+            Self::Weekly => {
+                let week = date.iso_week();
+
+                week.year() as i64 * 100 + week.week() as i64
+            }
+            Self::Monthly => date.year() as i64 * 12 + date.month0() as i64,
+            Self::Yearly => date.year() as i64,
+        }
+    }
+
+    /// Identifier used by [RetentionPolicy::retain] to decide whether two dates fall into the same period.
+    ///
+    /// Equal to [Period::bucket] except for months, which are identified by the month of the year.
+    /// The same month of different years counts as a single month.
+    pub fn category(&self, date: &DateTime<Utc>) -> i64 {
+        match self {
+            Self::Monthly => date.month0() as i64,
+            _ => self.bucket(date),
+        }
+    }
+}
+
 impl RetentionPolicy {
-    pub fn retain(&self, files: Vec<RetentionFile>) -> (Vec<RetentionFile>, Vec<RetentionFile>) {
-        if self == &RetentionPolicy::default() {
+    /// The configured periods in the order they are applied.
+    pub fn periods(&self) -> impl Iterator<Item = (Period, usize)> {
+        [
+            (Period::Daily, self.keep_daily),
+            (Period::Weekly, self.keep_weekly),
+            (Period::Monthly, self.keep_monthly),
+            (Period::Yearly, self.keep_yearly),
+        ]
+            .into_iter()
+            .filter_map(|(period, count)| count.map(|count| (period, count)))
+    }
+
+    /// Returns a policy where every field set in `overrides` replaces the value of `self`.
+    pub fn merge(&self, overrides: &RetentionPolicy) -> RetentionPolicy {
+        RetentionPolicy {
+            keep_last: overrides.keep_last.or(self.keep_last),
+            keep_hourly: overrides.keep_hourly.or(self.keep_hourly),
+            keep_daily: overrides.keep_daily.or(self.keep_daily),
+            keep_weekly: overrides.keep_weekly.or(self.keep_weekly),
+            keep_monthly: overrides.keep_monthly.or(self.keep_monthly),
+            keep_yearly: overrides.keep_yearly.or(self.keep_yearly),
+        }
+    }
+
+    /// Returns a policy where rules set to `0` are unset, as both don't keep any files.
+    pub fn without_disabled_rules(&self) -> RetentionPolicy {
+        let enabled = |count: Option<usize>| count.filter(|count| *count > 0);
+
+        RetentionPolicy {
+            keep_last: enabled(self.keep_last),
+            keep_hourly: enabled(self.keep_hourly),
+            keep_daily: enabled(self.keep_daily),
+            keep_weekly: enabled(self.keep_weekly),
+            keep_monthly: enabled(self.keep_monthly),
+            keep_yearly: enabled(self.keep_yearly),
+        }
+    }
+
+    /// Whether the policy contains no rule keeping any files, in which case all files are kept.
+    pub fn is_empty(&self) -> bool {
+        self.without_disabled_rules() == RetentionPolicy::default()
+    }
+
+    /// Splits the items into the ones to keep and the ones to drop.
+    ///
+    /// `files` have to be sorted by timestamp, newest first.
+    pub fn retain<T: Timestamped>(&self, files: Vec<T>) -> (Vec<T>, Vec<T>) {
+        if self.is_empty() {
             return (files, Default::default());
         }
 
@@ -25,17 +152,8 @@ impl RetentionPolicy {
                 }
             }
         }
-        if let Some(daily) = self.keep_daily {
-            retain_items(&mut files, &mut keep, &mut drop, daily, |file| file.date.date_naive());
-        }
-        if let Some(weekly) = self.keep_weekly {
-            retain_items(&mut files, &mut keep, &mut drop, weekly, |file| file.date.iso_week());
-        }
-        if let Some(monthly) = self.keep_monthly {
-            retain_items(&mut files, &mut keep, &mut drop, monthly, |file| file.date.month());
-        }
-        if let Some(yearly) = self.keep_yearly {
-            retain_items(&mut files, &mut keep, &mut drop, yearly, |file| file.date.year());
+        for (period, count) in self.periods() {
+            retain_items(&mut files, &mut keep, &mut drop, count, |file| period.category(&file.timestamp()));
         }
 
         for file in files {
@@ -46,7 +164,7 @@ impl RetentionPolicy {
     }
 }
 
-fn retain_items<I: Iterator<Item=RetentionFile>, F: Eq + Hash>(files: &mut I, keep: &mut Vec<RetentionFile>, drop: &mut Vec<RetentionFile>, count: usize, get_identifier: impl Fn(&RetentionFile) -> F) {
+fn retain_items<T: Timestamped, I: Iterator<Item=T>, F: Eq + Hash>(files: &mut I, keep: &mut Vec<T>, drop: &mut Vec<T>, count: usize, get_identifier: impl Fn(&T) -> F) {
     let mut categories = HashSet::new();
     for day in keep.iter() {
         categories.insert(get_identifier(day));
@@ -79,14 +197,68 @@ mod tests {
     use crate::config::RetentionPolicy;
     use crate::file::RetentionFile;
 
+    #[test]
+    fn retain_should_accept_timestamps() {
+        let dates: Vec<DateTime<Utc>> = (1..=10).rev()
+            .map(|day| Utc.with_ymd_and_hms(2024, 5, day, 12, 0, 0).unwrap())
+            .collect();
+        let policy = RetentionPolicy {
+            keep_last: Some(2),
+            keep_weekly: Some(2),
+            ..Default::default()
+        };
+
+        let (keep, drop) = policy.retain(dates.clone());
+
+        assert_eq!(vec![dates[0], dates[1], dates[5]], keep);
+        assert_eq!(7, drop.len());
+    }
+
+    #[test]
+    fn merge_should_override_set_fields() {
+        let base = RetentionPolicy {
+            keep_last: Some(1),
+            keep_daily: Some(7),
+            keep_monthly: Some(3),
+            ..Default::default()
+        };
+        let overrides = RetentionPolicy {
+            keep_daily: Some(14),
+            keep_yearly: Some(2),
+            ..Default::default()
+        };
+
+        let policy = base.merge(&overrides);
+
+        assert_eq!(RetentionPolicy {
+            keep_last: Some(1),
+            keep_daily: Some(14),
+            keep_monthly: Some(3),
+            keep_yearly: Some(2),
+            ..Default::default()
+        }, policy);
+    }
+
+    #[test_case(vec!["2022-01-01", "2021-12-31", "2021-11-30", "2021-10-31"])]
+    fn retain_should_ignore_zero_counts(files: Vec<&'static str>) {
+        let policy = RetentionPolicy {
+            keep_daily: Some(2),
+            ..Default::default()
+        };
+        let unset_policy = RetentionPolicy {
+            keep_monthly: Some(0),
+            ..policy
+        };
+        let (files, _, _) = create_daily_expected(files);
+
+        assert_eq!(policy.retain(files.clone()), unset_policy.retain(files));
+    }
+
     #[test_case(2)]
     #[test_case(5)]
     fn retain_should_drop_no_files_for_empty_policy(count: usize) {
         let policy = RetentionPolicy::default();
-        let file = RetentionFile {
-            date: Default::default(),
-            filename: Default::default(),
-        };
+        let file = RetentionFile::default();
         let files = vec![file; count];
 
         let (keep, drop) = policy.retain(files);
@@ -95,6 +267,17 @@ mod tests {
         assert_eq!(0, drop.len());
     }
 
+    #[test_case(RetentionPolicy { keep_daily: Some(0), ..Default::default() })]
+    #[test_case(RetentionPolicy { keep_last: Some(0), keep_monthly: Some(0), ..Default::default() })]
+    fn retain_should_drop_no_files_if_all_rules_are_zero(policy: RetentionPolicy) {
+        let files = vec![RetentionFile::default(); 3];
+
+        let (keep, drop) = policy.retain(files);
+
+        assert_eq!(3, keep.len());
+        assert_eq!(0, drop.len());
+    }
+
     #[test_case(2, 1)]
     #[test_case(5, 2)]
     fn retain_should_keep_last_files(total: usize, last: usize) {
@@ -102,10 +285,7 @@ mod tests {
             keep_last: Some(last),
             ..Default::default()
         };
-        let file = RetentionFile {
-            date: Default::default(),
-            filename: Default::default(),
-        };
+        let file = RetentionFile::default();
         let files = vec![file; total];
 
         let (keep, drop) = policy.retain(files);
@@ -237,7 +417,7 @@ mod tests {
             .map(|(date, should_keep)| {
                 let file = RetentionFile {
                     date,
-                    filename: Default::default(),
+                    ..Default::default()
                 };
                 (file, should_keep)
             })
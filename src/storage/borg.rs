@@ -0,0 +1,161 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use serde::Deserialize;
+
+use crate::file::FileEntry;
+use crate::storage::command::command_line;
+use crate::storage::{CommandRunner, Result, Storage, StorageError, SystemRunner};
+
+/// Amount of archives deleted by a single `borg delete`.
+const BATCH_SIZE: usize = 100;
+
+/// Archives of a borg repository, accessed using the `borg` CLI.
+///
+/// Deleting archives doesn't free space in the repository until `borg compact` runs.
+pub struct BorgStorage {
+    repository: String,
+    runner: Arc<dyn CommandRunner>,
+}
+
+#[derive(Deserialize)]
+struct ArchiveList {
+    archives: Vec<Archive>,
+}
+
+#[derive(Deserialize)]
+struct Archive {
+    name: String,
+    /// Local time without offset, e.g. `2024-05-01T02:00:03.000000`
+    time: String,
+}
+
+impl BorgStorage {
+    /// Parses `borg://<repository>`, e.g. `borg:///var/backups/borg` or `borg://ssh://backup@host/./borg`.
+    pub fn new(url: &str) -> Result<Self> {
+        Self::with_runner(url, Arc::new(SystemRunner))
+    }
+
+    pub fn with_runner(url: &str, runner: Arc<dyn CommandRunner>) -> Result<Self> {
+        let repository = url.strip_prefix("borg://").unwrap_or_default();
+        if repository.is_empty() {
+            return Err(StorageError::InvalidUrl { url: url.to_string(), reason: "Missing repository".into() });
+        }
+
+        Ok(Self { repository: repository.to_string(), runner })
+    }
+}
+
+impl Storage for BorgStorage {
+    fn list(&self) -> Result<Box<dyn Iterator<Item = FileEntry> + '_>> {
+        let args = ["list", "--json", self.repository.as_str()];
+        let output = self.runner.run("borg", &args)?;
+        let list: ArchiveList = serde_json::from_str(&output).map_err(|err| StorageError::Command {
+            command: command_line("borg", &args),
+            message: format!("Invalid output: {err}"),
+        })?;
+        let entries = list.archives.into_iter().map(|archive| FileEntry {
+            modified: NaiveDateTime::parse_from_str(&archive.time, "%Y-%m-%dT%H:%M:%S%.f")
+                .ok()
+                .and_then(|time| Local.from_local_datetime(&time).single())
+                .map(DateTime::from),
+            size: None,
+            name: archive.name,
+        });
+
+        Ok(Box::new(entries))
+    }
+
+    fn remove(&self, name: &str) -> Result<()> {
+        self.remove_all(&[name])
+    }
+
+    /// Deletes the archives using a single `borg delete -- <repository> <archive>...`.
+    fn remove_all(&self, names: &[&str]) -> Result<()> {
+        // Without any archive borg deletes the whole repository
+        if names.is_empty() {
+            return Ok(());
+        }
+        let args: Vec<&str> = ["delete", "--", self.repository.as_str()].into_iter().chain(names.iter().copied()).collect();
+
+        self.runner.run("borg", &args).map(|_| ())
+    }
+
+    fn batch_size(&self) -> usize {
+        BATCH_SIZE
+    }
+
+    /// Renames the archive using `borg rename -- <repository>::<from> <to>`.
+    fn rename(&self, from: &str, to: &str) -> Result<()> {
+        let archive = format!("{}::{from}", self.repository);
+
+        self.runner.run("borg", &["rename", "--", &archive, to]).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::command::tests::FakeRunner;
+
+    use super::*;
+
+    const ARCHIVES: &str = include_str!("../../tests/fixtures/borg-list.json");
+
+    #[test]
+    fn list_should_parse_archives() {
+        let storage = BorgStorage::with_runner("borg:///var/backups/borg", Arc::new(FakeRunner::new(|_| Ok(ARCHIVES.to_string())))).unwrap();
+
+        let entries: Vec<_> = storage.list().unwrap().collect();
+
+        let names: Vec<_> = entries.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(vec!["web1-2024-05-01T02:00:03", "web1-2024-05-02T02:00:04", "web1-2024-05-03T02:00:02", "manual-before-upgrade"], names);
+        let expected = Local.with_ymd_and_hms(2024, 5, 1, 2, 0, 3).unwrap();
+        assert_eq!(Some(DateTime::from(expected)), entries[0].modified);
+    }
+
+    #[test]
+    fn list_should_report_invalid_output() {
+        let storage = BorgStorage::with_runner("borg:///var/backups/borg", Arc::new(FakeRunner::new(|_| Ok("Repository is locked".to_string())))).unwrap();
+
+        let result = storage.list();
+
+        assert!(matches!(result, Err(StorageError::Command { command, .. }) if command == "borg list --json /var/backups/borg"));
+    }
+
+    #[test]
+    fn remove_all_should_delete_archives_in_single_command() {
+        let runner = Arc::new(FakeRunner::new(|_| Ok(String::new())));
+        let storage = BorgStorage::with_runner("borg://ssh://backup@host/./borg", runner.clone()).unwrap();
+
+        storage.remove_all(&["web1-2024-05-01T02:00:03", "web1-2024-05-02T02:00:04"]).unwrap();
+
+        assert_eq!(vec!["borg delete -- ssh://backup@host/./borg web1-2024-05-01T02:00:03 web1-2024-05-02T02:00:04"], *runner.commands.lock().unwrap());
+    }
+
+    #[test]
+    fn remove_all_should_not_run_borg_without_archives() {
+        let runner = Arc::new(FakeRunner::new(|_| Ok(String::new())));
+        let storage = BorgStorage::with_runner("borg:///var/backups/borg", runner.clone()).unwrap();
+
+        storage.remove_all(&[]).unwrap();
+
+        assert!(runner.commands.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn remove_all_should_not_parse_archives_as_options() {
+        let runner = Arc::new(FakeRunner::new(|_| Ok(String::new())));
+        let storage = BorgStorage::with_runner("borg:///var/backups/borg", runner.clone()).unwrap();
+
+        storage.remove_all(&["--force"]).unwrap();
+
+        assert_eq!(vec!["borg delete -- /var/backups/borg --force"], *runner.commands.lock().unwrap());
+    }
+
+    #[test]
+    fn new_should_reject_missing_repository() {
+        let result = BorgStorage::new("borg://");
+
+        assert!(matches!(result, Err(StorageError::InvalidUrl { .. })));
+    }
+}
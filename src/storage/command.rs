@@ -0,0 +1,71 @@
+use std::process::Command;
+
+use crate::storage::{Result, StorageError};
+
+/// Runs external programs for storages wrapping a CLI, replaced by a fake in tests.
+pub trait CommandRunner: Send + Sync {
+    /// Runs the program and returns its stdout, failing if it doesn't exit successfully.
+    fn run(&self, program: &str, args: &[&str]) -> Result<String>;
+}
+
+/// Runs the programs found in `PATH`, inheriting the environment, e.g. `BORG_PASSPHRASE` or `RESTIC_PASSWORD`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemRunner;
+
+impl CommandRunner for SystemRunner {
+    fn run(&self, program: &str, args: &[&str]) -> Result<String> {
+        tracing::trace!(program, ?args, "Running command");
+        let output = Command::new(program)
+            .args(args)
+            .output()
+            .map_err(|err| StorageError::Command { command: command_line(program, args), message: err.to_string() })?;
+
+        if output.status.success() {
+            Ok(String::from_utf8_lossy(&output.stdout).to_string())
+        } else {
+            Err(StorageError::Command {
+                command: command_line(program, args),
+                message: format!("{}: {}", output.status, String::from_utf8_lossy(&output.stderr).trim()),
+            })
+        }
+    }
+}
+
+/// Joins the program and its arguments for error messages.
+pub(crate) fn command_line(program: &str, args: &[&str]) -> String {
+    std::iter::once(program).chain(args.iter().copied()).collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    /// Answers every command using `handler` and records the command lines.
+    pub struct FakeRunner<F> {
+        handler: F,
+        pub commands: Mutex<Vec<String>>,
+    }
+
+    impl<F: Fn(&[&str]) -> Result<String> + Send + Sync> FakeRunner<F> {
+        pub fn new(handler: F) -> Self {
+            Self { handler, commands: Mutex::new(Vec::new()) }
+        }
+    }
+
+    impl<F: Fn(&[&str]) -> Result<String> + Send + Sync> CommandRunner for FakeRunner<F> {
+        fn run(&self, program: &str, args: &[&str]) -> Result<String> {
+            self.commands.lock().unwrap().push(command_line(program, args));
+
+            (self.handler)(args)
+        }
+    }
+
+    #[test]
+    fn system_runner_should_report_failures() {
+        let result = SystemRunner.run("sh", &["-c", "echo broken >&2; exit 3"]);
+
+        assert!(matches!(result, Err(StorageError::Command { message, .. }) if message.contains("broken")));
+    }
+}
@@ -0,0 +1,92 @@
+use std::fs;
+use std::path::PathBuf;
+
+use crate::file::FileEntry;
+use crate::storage::{Result, Storage, StorageError};
+
+/// Directory on the local filesystem.
+///
+/// Entries are listed without their metadata, which would take another syscall per entry on large or network filesystems.
+pub struct LocalStorage {
+    path: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+}
+
+impl Storage for LocalStorage {
+    fn list(&self) -> Result<Box<dyn Iterator<Item = FileEntry> + '_>> {
+        let files = fs::read_dir(&self.path)
+            .map_err(|error| StorageError::Io { path: self.path.clone(), error })?
+            .flat_map(|dir| {
+                if let Err(err) = dir.as_ref() {
+                    tracing::warn!("Error while reading directory {:?}: {err:?}", self.path);
+                }
+
+                dir.ok()
+            })
+            .map(|dir| FileEntry::new(dir.file_name().to_string_lossy().to_string()));
+
+        Ok(Box::new(files))
+    }
+
+    /// Removes the file or the directory including its contents.
+    fn remove(&self, name: &str) -> Result<()> {
+        let path = self.path.join(name);
+        if !path.exists() {
+            return Ok(());
+        }
+        let result = if path.is_dir() {
+            fs::remove_dir_all(&path)
+        } else {
+            fs::remove_file(&path)
+        };
+
+        result.map_err(|error| StorageError::Io { path, error })
+    }
+
+    /// Size of a file, directories have no size.
+    fn size(&self, name: &str) -> Option<u64> {
+        fs::metadata(self.path.join(name)).ok()
+            .filter(|metadata| metadata.is_file())
+            .map(|metadata| metadata.len())
+    }
+
+    fn rename(&self, from: &str, to: &str) -> Result<()> {
+        let path = self.path.join(from);
+
+        fs::rename(&path, self.path.join(to)).map_err(|error| StorageError::Io { path, error })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rename_should_move_file() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("2024-05-01.tar.partial"), "").unwrap();
+        let storage = LocalStorage::new(dir.path().to_path_buf());
+
+        storage.rename("2024-05-01.tar.partial", "2024-05-01.tar").unwrap();
+
+        let names: Vec<_> = storage.list().unwrap().map(|entry| entry.name).collect();
+        assert_eq!(vec!["2024-05-01.tar"], names);
+    }
+
+    #[test]
+    fn size_should_only_be_read_for_files() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("2024-05-01.tar"), "backup").unwrap();
+        fs::create_dir(dir.path().join("2024-05-02")).unwrap();
+        let storage = LocalStorage::new(dir.path().to_path_buf());
+
+        assert_eq!(Some(6), storage.size("2024-05-01.tar"));
+        assert_eq!(None, storage.size("2024-05-02"));
+        assert_eq!(None, storage.size("2024-05-03.tar"));
+    }
+}
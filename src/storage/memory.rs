@@ -0,0 +1,122 @@
+use std::io;
+use std::sync::Mutex;
+
+use crate::file::FileEntry;
+use crate::storage::{Result, Storage, StorageError};
+
+/// Entries held in memory, e.g. read from a listing to simulate a policy or for tests.
+#[derive(Default)]
+pub struct MemoryStorage {
+    entries: Mutex<Vec<FileEntry>>,
+    /// Entries passed through by the first [Storage::list] without being held in memory
+    source: Mutex<Option<Box<dyn Iterator<Item = FileEntry> + Send>>>,
+}
+
+impl MemoryStorage {
+    pub fn new(entries: impl IntoIterator<Item = FileEntry>) -> Self {
+        Self {
+            entries: Mutex::new(entries.into_iter().collect()),
+            source: Mutex::new(None),
+        }
+    }
+
+    /// Creates a storage which can only be listed once, streaming the entries from `source`.
+    ///
+    /// Used to simulate policies on large listings, e.g. read from stdin, without reading them into memory.
+    pub fn streaming(source: impl Iterator<Item = FileEntry> + Send + 'static) -> Self {
+        Self {
+            entries: Mutex::new(Vec::new()),
+            source: Mutex::new(Some(Box::new(source))),
+        }
+    }
+
+    /// Names of the remaining entries in the order they were added.
+    pub fn names(&self) -> Vec<String> {
+        self.entries.lock().unwrap().iter().map(|entry| entry.name.clone()).collect()
+    }
+}
+
+impl FromIterator<FileEntry> for MemoryStorage {
+    fn from_iter<T: IntoIterator<Item = FileEntry>>(entries: T) -> Self {
+        Self::new(entries)
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn list(&self) -> Result<Box<dyn Iterator<Item = FileEntry> + '_>> {
+        if let Some(source) = self.source.lock().unwrap().take() {
+            return Ok(source);
+        }
+
+        Ok(Box::new(self.entries.lock().unwrap().clone().into_iter()))
+    }
+
+    fn remove(&self, name: &str) -> Result<()> {
+        self.entries.lock().unwrap().retain(|entry| entry.name != name);
+
+        Ok(())
+    }
+
+    /// Renames the entry, replacing an existing entry with the new name like `rename` on unix.
+    fn rename(&self, from: &str, to: &str) -> Result<()> {
+        let mut entries = self.entries.lock().unwrap();
+        if !entries.iter().any(|entry| entry.name == from) {
+            return Err(StorageError::Io { path: from.into(), error: io::ErrorKind::NotFound.into() });
+        }
+        entries.retain(|entry| entry.name != to);
+        if let Some(entry) = entries.iter_mut().find(|entry| entry.name == from) {
+            entry.name = to.to_string();
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn storage() -> MemoryStorage {
+        ["2024-05-01", "2024-05-02", "2024-05-03"].into_iter()
+            .map(|name| FileEntry::new(name.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn remove_should_ignore_missing_entries() {
+        let storage = storage();
+
+        storage.remove_all(&["2024-05-01", "2024-04-30"]).unwrap();
+
+        assert_eq!(vec!["2024-05-02", "2024-05-03"], storage.names());
+    }
+
+    #[test]
+    fn streaming_should_only_be_listed_once() {
+        let storage = MemoryStorage::streaming(["2024-05-01", "2024-05-02"].into_iter().map(|name| FileEntry::new(name.to_string())));
+
+        let first: Vec<_> = storage.list().unwrap().map(|entry| entry.name).collect();
+        let second = storage.list().unwrap().count();
+
+        assert_eq!(vec!["2024-05-01", "2024-05-02"], first);
+        assert_eq!(0, second);
+    }
+
+    #[test]
+    fn rename_should_replace_existing_entry() {
+        let storage = storage();
+
+        storage.rename("2024-05-01", "2024-05-03").unwrap();
+
+        assert_eq!(vec!["2024-05-03", "2024-05-02"], storage.names());
+    }
+
+    #[test]
+    fn rename_should_fail_for_missing_entry() {
+        let storage = storage();
+
+        let result = storage.rename("2024-04-30", "2024-05-04");
+
+        assert!(matches!(result, Err(StorageError::Io { .. })));
+    }
+}
@@ -0,0 +1,384 @@
+use std::env;
+use std::time::Duration;
+
+use chrono::DateTime;
+use rusty_s3::actions::{DeleteObjects, DeleteObjectsResponse, ListObjectsV2, ObjectIdentifier};
+use rusty_s3::{Bucket, Credentials, S3Action, UrlStyle};
+use url::Url;
+
+use crate::file::FileEntry;
+use crate::storage::{http_error, Result, Storage, StorageError};
+
+/// Maximum amount of objects deleted by a single `DeleteObjects` request.
+const BATCH_SIZE: usize = 1000;
+/// Validity of the signature of a request.
+const SIGNATURE_TTL: Duration = Duration::from_secs(60);
+const DEFAULT_REGION: &str = "us-east-1";
+
+/// Objects with a common prefix in a bucket of an S3 compatible object storage.
+///
+/// Only objects directly below the prefix are listed, keys containing another `/` are ignored like subdirectories.
+pub struct S3Storage {
+    bucket: Bucket,
+    /// Either empty or ending with `/`
+    prefix: String,
+    credentials: Option<Credentials>,
+    agent: ureq::Agent,
+}
+
+impl S3Storage {
+    /// Parses `s3://bucket/prefix/`.
+    ///
+    /// The endpoint is read from `AWS_ENDPOINT_URL`, e.g. `http://localhost:9000` for MinIO, and defaults to AWS.
+    /// The region is read from `AWS_REGION` or `AWS_DEFAULT_REGION`, the credentials from `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY`.
+    pub fn new(url: &str) -> Result<Self> {
+        let region = env::var("AWS_REGION")
+            .or_else(|_| env::var("AWS_DEFAULT_REGION"))
+            .unwrap_or_else(|_| DEFAULT_REGION.to_string());
+        let endpoint = match env::var("AWS_ENDPOINT_URL") {
+            Ok(endpoint) => Some(Url::parse(&endpoint).map_err(|err| StorageError::InvalidUrl {
+                url: endpoint,
+                reason: err.to_string(),
+            })?),
+            Err(_) => None,
+        };
+
+        Self::with_endpoint(url, endpoint, region, Credentials::from_env())
+    }
+
+    fn with_endpoint(url: &str, endpoint: Option<Url>, region: String, credentials: Option<Credentials>) -> Result<Self> {
+        let invalid = |reason: String| StorageError::InvalidUrl { url: url.to_string(), reason };
+        let Some(("s3", location)) = url.split_once("://") else {
+            return Err(invalid("Expected s3://".into()));
+        };
+        let (name, prefix) = location.split_once('/').unwrap_or((location, ""));
+        if name.is_empty() {
+            return Err(invalid("Missing bucket".into()));
+        }
+        let prefix = match prefix.trim_matches('/') {
+            "" => String::new(),
+            prefix => format!("{prefix}/"),
+        };
+        // Custom endpoints like MinIO usually don't support virtual hosted buckets
+        let (endpoint, style) = match endpoint {
+            Some(endpoint) => (endpoint, UrlStyle::Path),
+            None => (
+                Url::parse(&format!("https://s3.{region}.amazonaws.com")).map_err(|err| invalid(err.to_string()))?,
+                UrlStyle::VirtualHost,
+            ),
+        };
+        let bucket = Bucket::new(endpoint, style, name.to_string(), region).map_err(|err| invalid(err.to_string()))?;
+
+        Ok(Self {
+            bucket,
+            prefix,
+            credentials,
+            agent: ureq::Agent::new(),
+        })
+    }
+
+    fn call(&self, request: ureq::Request, body: Option<&str>) -> Result<ureq::Response> {
+        let url = redact(request.url());
+        let result = match body {
+            Some(body) => request.send_string(body),
+            None => request.call(),
+        };
+
+        result.map_err(|err| http_error(url, err))
+    }
+
+    /// Lists a page of objects starting at `continuation_token` and returns the token of the next page.
+    fn list_page(&self, continuation_token: Option<String>) -> Result<(Vec<FileEntry>, Option<String>)> {
+        let mut action = ListObjectsV2::new(&self.bucket, self.credentials.as_ref());
+        action.with_prefix(self.prefix.as_str());
+        action.with_delimiter("/");
+        if let Some(token) = continuation_token {
+            action.with_continuation_token(token);
+        }
+        let url = action.sign(SIGNATURE_TTL);
+        let response = self.call(self.agent.request_url("GET", &url), None)?;
+        let response = response.into_string().map_err(|error| StorageError::Http {
+            url: redact(url.as_str()),
+            message: error.to_string(),
+        })?;
+        let response = ListObjectsV2::parse_response(&response).map_err(|error| StorageError::Http {
+            url: redact(url.as_str()),
+            message: format!("Invalid response: {error}"),
+        })?;
+        let entries = response.contents.into_iter()
+            .map(|object| FileEntry {
+                name: object.key.strip_prefix(&self.prefix).unwrap_or(&object.key).to_string(),
+                size: Some(object.size),
+                modified: DateTime::parse_from_rfc3339(&object.last_modified).ok().map(DateTime::from),
+            })
+            .collect();
+
+        Ok((entries, response.next_continuation_token))
+    }
+
+    fn delete(&self, names: &[&str]) -> Result<()> {
+        let objects: Vec<_> = names.iter().map(|name| ObjectIdentifier::new(format!("{}{name}", self.prefix))).collect();
+        let mut action = DeleteObjects::new(&self.bucket, self.credentials.as_ref(), objects.iter());
+        action.set_quiet(true);
+        let url = action.sign(SIGNATURE_TTL);
+        let (body, md5) = action.body_with_md5();
+        tracing::trace!(count = names.len(), "Deleting objects");
+
+        let response = self.call(self.agent.request_url("POST", &url).set("Content-MD5", &md5), Some(&body))?;
+        let response = response.into_string().map_err(|error| StorageError::Http {
+            url: redact(url.as_str()),
+            message: error.to_string(),
+        })?;
+        let response = DeleteObjectsResponse::parse(&response).map_err(|error| StorageError::Http {
+            url: redact(url.as_str()),
+            message: format!("Invalid response: {error}"),
+        })?;
+        // Missing objects aren't reported as errors by S3
+        match response.errors.first() {
+            Some(error) => Err(StorageError::Http {
+                url: redact(url.as_str()),
+                message: format!("Unable to delete {} of {} objects, {}: {} {}", response.errors.len(), names.len(), error.key, error.code, error.message),
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Storage for S3Storage {
+    /// Fetches the first page of objects immediately and the following pages while iterating.
+    fn list(&self) -> Result<Box<dyn Iterator<Item = FileEntry> + '_>> {
+        let (entries, continuation_token) = self.list_page(None)?;
+
+        Ok(Box::new(Objects {
+            storage: self,
+            entries: entries.into_iter(),
+            continuation_token,
+        }))
+    }
+
+    fn remove(&self, name: &str) -> Result<()> {
+        self.delete(&[name])
+    }
+
+    /// Deletes the objects using a single `DeleteObjects` request.
+    fn remove_all(&self, names: &[&str]) -> Result<()> {
+        self.delete(names)
+    }
+
+    fn batch_size(&self) -> usize {
+        BATCH_SIZE
+    }
+}
+
+/// Objects of a listing, the next page is requested once the current one is consumed.
+struct Objects<'a> {
+    storage: &'a S3Storage,
+    entries: std::vec::IntoIter<FileEntry>,
+    continuation_token: Option<String>,
+}
+
+impl Iterator for Objects<'_> {
+    type Item = FileEntry;
+
+    fn next(&mut self) -> Option<FileEntry> {
+        loop {
+            if let Some(entry) = self.entries.next() {
+                return Some(entry);
+            }
+            let token = self.continuation_token.take()?;
+            match self.storage.list_page(Some(token)) {
+                Ok((entries, continuation_token)) => {
+                    self.entries = entries.into_iter();
+                    self.continuation_token = continuation_token;
+                }
+                Err(err) => {
+                    tracing::warn!("Error while listing the next page of objects: {err}");
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+/// Removes the query containing the signature from a url so it can be logged.
+fn redact(url: &str) -> String {
+    url.split_once('?').map(|(url, _)| url).unwrap_or(url).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use crate::config::{Config, RetentionFilePattern, RetentionPath, RetentionPolicy};
+    use crate::executor::ExecutionContext;
+    use crate::storage::tests::serve;
+
+    use super::*;
+
+    fn storage(address: &str, url: &str) -> S3Storage {
+        let endpoint = Url::parse(&format!("http://{address}")).unwrap();
+
+        S3Storage::with_endpoint(url, Some(endpoint), DEFAULT_REGION.into(), Some(Credentials::new("key", "secret"))).unwrap()
+    }
+
+    fn list_response(keys: &[&str], token: Option<&str>) -> String {
+        let contents: String = keys.iter()
+            .map(|key| format!("<Contents><Key>{key}</Key><LastModified>2024-05-01T12:00:00.000Z</LastModified><ETag>\"0\"</ETag><Size>42</Size><StorageClass>STANDARD</StorageClass></Contents>"))
+            .collect();
+        let token = token.map(|token| format!("<NextContinuationToken>{token}</NextContinuationToken>")).unwrap_or_default();
+
+        format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?><ListBucketResult xmlns=\"http://s3.amazonaws.com/doc/2006-03-01/\"><Name>backups</Name><Prefix>pg/</Prefix><KeyCount>{}</KeyCount><MaxKeys>1000</MaxKeys><IsTruncated>{}</IsTruncated>{contents}{token}</ListBucketResult>", keys.len(), !token.is_empty())
+    }
+
+    #[test_case("s3://backups/pg/", "pg/")]
+    #[test_case("s3://backups/pg", "pg/" ; "without trailing slash")]
+    #[test_case("s3://backups/team/pg/", "team/pg/")]
+    #[test_case("s3://backups", "")]
+    #[test_case("s3://backups/", "" ; "trailing slash")]
+    fn new_should_parse_prefix(url: &str, prefix: &str) {
+        let storage = storage("localhost:9000", url);
+
+        assert_eq!("backups", storage.bucket.name());
+        assert_eq!(prefix, storage.prefix);
+    }
+
+    #[test_case("s3:///pg/")]
+    #[test_case("oci://backups/pg/")]
+    fn new_should_reject_invalid_url(url: &str) {
+        let result = S3Storage::with_endpoint(url, None, DEFAULT_REGION.into(), None);
+
+        assert!(matches!(result, Err(StorageError::InvalidUrl { .. })));
+    }
+
+    #[test]
+    fn list_should_follow_continuation_token() {
+        let (address, requests) = serve(|_, path, _| {
+            if path.contains("continuation-token=next") {
+                (200, vec![], list_response(&["pg/2024-05-03.tar"], None))
+            } else {
+                (200, vec![], list_response(&["pg/2024-05-01.tar", "pg/2024-05-02.tar"], Some("next")))
+            }
+        });
+        let storage = storage(&address, "s3://backups/pg/");
+
+        let entries: Vec<_> = storage.list().unwrap().collect();
+
+        let names: Vec<_> = entries.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(vec!["2024-05-01.tar", "2024-05-02.tar", "2024-05-03.tar"], names);
+        assert_eq!(Some(42), entries[0].size);
+        assert_eq!(Some("2024-05-01T12:00:00+00:00".into()), entries[0].modified.map(|modified| modified.to_rfc3339()));
+        let requests = requests.lock().unwrap();
+        assert!(requests[0].path.starts_with("/backups/?"));
+        assert!(requests[0].path.contains("prefix=pg%2F"));
+        assert!(requests[0].path.contains("X-Amz-Signature="));
+    }
+
+    #[test]
+    fn list_should_request_next_page_once_consumed() {
+        let (address, requests) = serve(|_, path, _| {
+            if path.contains("continuation-token=next") {
+                (200, vec![], list_response(&["pg/2024-05-03.tar"], None))
+            } else {
+                (200, vec![], list_response(&["pg/2024-05-01.tar", "pg/2024-05-02.tar"], Some("next")))
+            }
+        });
+        let storage = storage(&address, "s3://backups/pg/");
+
+        let mut entries = storage.list().unwrap();
+        entries.by_ref().take(2).for_each(drop);
+
+        assert_eq!(1, requests.lock().unwrap().len());
+        assert_eq!(Some("2024-05-03.tar".into()), entries.next().map(|entry| entry.name));
+        assert_eq!(2, requests.lock().unwrap().len());
+    }
+
+    #[test]
+    fn list_should_stop_at_failing_page() {
+        let (address, _) = serve(|_, path, _| {
+            if path.contains("continuation-token=next") {
+                (500, vec![], "<Error><Code>InternalError</Code></Error>".into())
+            } else {
+                (200, vec![], list_response(&["pg/2024-05-01.tar"], Some("next")))
+            }
+        });
+        let storage = storage(&address, "s3://backups/pg/");
+
+        let names: Vec<_> = storage.list().unwrap().map(|entry| entry.name).collect();
+
+        assert_eq!(vec!["2024-05-01.tar"], names);
+    }
+
+    #[test]
+    fn list_should_fail_for_failing_first_page() {
+        let (address, _) = serve(|_, _, _| (403, vec![], "<Error><Code>AccessDenied</Code></Error>".into()));
+        let storage = storage(&address, "s3://backups/pg/");
+
+        let result = storage.list();
+
+        assert!(matches!(result, Err(StorageError::Http { .. })));
+    }
+
+    #[test]
+    fn remove_all_should_delete_objects_in_single_request() {
+        let (address, requests) = serve(|_, _, _| (200, vec![], "<DeleteResult xmlns=\"http://s3.amazonaws.com/doc/2006-03-01/\"></DeleteResult>".into()));
+        let storage = storage(&address, "s3://backups/pg/");
+
+        storage.remove_all(&["2024-05-01.tar", "2024-05-02.tar"]).unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(1, requests.len());
+        assert_eq!("POST", requests[0].method);
+        assert!(requests[0].path.starts_with("/backups/?"));
+        assert!(requests[0].path.contains("&delete=1&"));
+        assert!(requests[0].body.contains("<Key>pg/2024-05-01.tar</Key>"));
+        assert!(requests[0].body.contains("<Key>pg/2024-05-02.tar</Key>"));
+    }
+
+    #[test]
+    fn remove_all_should_report_failed_objects() {
+        let (address, _) = serve(|_, _, _| (200, vec![], "<DeleteResult xmlns=\"http://s3.amazonaws.com/doc/2006-03-01/\"><Error><Key>pg/2024-05-01.tar</Key><Code>AccessDenied</Code><Message>Access Denied</Message></Error></DeleteResult>".into()));
+        let storage = storage(&address, "s3://backups/pg/");
+
+        let result = storage.remove_all(&["2024-05-01.tar", "2024-05-02.tar"]);
+
+        assert!(matches!(result, Err(StorageError::Http { message, .. }) if message.contains("pg/2024-05-01.tar: AccessDenied")));
+    }
+
+    #[test_case("http://localhost:9000/backups?delete=&X-Amz-Signature=abc", "http://localhost:9000/backups")]
+    #[test_case("http://localhost:9000/backups", "http://localhost:9000/backups")]
+    fn redact_should_remove_signature(url: &str, expected: &str) {
+        assert_eq!(expected, redact(url));
+    }
+
+    #[test]
+    #[ignore = "requires MinIO, see `just test-s3`"]
+    fn s3_integration_should_prune_objects() {
+        // The executor reads the endpoint and credentials from the environment as well
+        let endpoint = Url::parse(&env::var("AWS_ENDPOINT_URL").expect("AWS_ENDPOINT_URL is set")).unwrap();
+        let credentials = Credentials::from_env().expect("AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY are set");
+        let name = format!("file-retention-policy-{}", std::process::id());
+        let bucket = Bucket::new(endpoint.clone(), UrlStyle::Path, name.clone(), DEFAULT_REGION).unwrap();
+        ureq::put(bucket.create_bucket(&credentials).sign(SIGNATURE_TTL).as_str()).call().unwrap();
+        for key in ["pg/2024-05-01.tar", "pg/2024-05-02.tar", "pg/2024-05-03.tar", "pg/README", "pg/nested/2024-05-01.tar"] {
+            ureq::put(bucket.put_object(Some(&credentials), key).sign(SIGNATURE_TTL).as_str()).send_string(key).unwrap();
+        }
+        let pattern = "{year}-{month}-{day}.tar".parse::<RetentionFilePattern>().unwrap();
+        let url = format!("s3://{name}/pg/");
+        let config = Config {
+            retention: RetentionPolicy {
+                keep_last: Some(1),
+                ..Default::default()
+            },
+            profiles: Default::default(),
+            deletion: Default::default(),
+            paths: vec![RetentionPath::new(url.clone().into(), pattern.compile().unwrap())],
+        };
+        let report = ExecutionContext::Default.run(&config, 1).unwrap();
+
+        let storage = S3Storage::new(&url).unwrap();
+        let mut names: Vec<_> = storage.list().unwrap().map(|entry| entry.name).collect();
+        names.sort();
+        assert_eq!((1, 2, 1), (report.kept, report.dropped, report.unparseable));
+        assert_eq!(vec!["2024-05-03.tar", "README"], names);
+    }
+}
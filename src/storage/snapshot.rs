@@ -0,0 +1,257 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use chrono::DateTime;
+
+use crate::file::FileEntry;
+use crate::storage::command::command_line;
+use crate::storage::{CommandRunner, LocalStorage, Result, Storage, StorageError, SystemRunner};
+
+/// Amount of snapshots destroyed by a single command.
+const BATCH_SIZE: usize = 100;
+/// Inode number of the top directory of every btrfs subvolume.
+const SUBVOLUME_INODE: u64 = 256;
+
+/// Snapshots of a ZFS dataset, accessed using the `zfs` CLI.
+///
+/// Entries are named after the part following the `@`, e.g. `auto-2024-05-01_00-00` for `tank/data@auto-2024-05-01_00-00`.
+/// Snapshots of child datasets are ignored.
+pub struct ZfsStorage {
+    dataset: String,
+    runner: Arc<dyn CommandRunner>,
+}
+
+impl ZfsStorage {
+    /// Parses `zfs://<dataset>`, e.g. `zfs://tank/data`.
+    pub fn new(url: &str) -> Result<Self> {
+        Self::with_runner(url, Arc::new(SystemRunner))
+    }
+
+    pub fn with_runner(url: &str, runner: Arc<dyn CommandRunner>) -> Result<Self> {
+        let dataset = url.strip_prefix("zfs://").unwrap_or_default().trim_matches('/');
+        if dataset.is_empty() {
+            return Err(StorageError::InvalidUrl { url: url.to_string(), reason: "Missing dataset".into() });
+        }
+        if dataset.contains('@') {
+            return Err(StorageError::InvalidUrl { url: url.to_string(), reason: "Expected a dataset instead of a snapshot".into() });
+        }
+
+        Ok(Self { dataset: dataset.to_string(), runner })
+    }
+
+    /// Parses a line of `zfs list -H -p -o name,used,creation`.
+    fn parse_line(&self, line: &str) -> Option<FileEntry> {
+        let mut columns = line.split('\t');
+        let (dataset, name) = columns.next()?.split_once('@')?;
+        if dataset != self.dataset {
+            return None;
+        }
+
+        Some(FileEntry {
+            name: name.to_string(),
+            size: columns.next().and_then(|used| used.parse().ok()),
+            modified: columns.next().and_then(|creation| creation.parse().ok()).and_then(|creation| DateTime::from_timestamp(creation, 0)),
+        })
+    }
+}
+
+impl Storage for ZfsStorage {
+    fn list(&self) -> Result<Box<dyn Iterator<Item = FileEntry> + '_>> {
+        let args = ["list", "-t", "snapshot", "-H", "-p", "-o", "name,used,creation", "-d", "1", self.dataset.as_str()];
+        let output = self.runner.run("zfs", &args)?;
+        let entries: Vec<_> = output.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| self.parse_line(line).ok_or_else(|| StorageError::Command {
+                command: command_line("zfs", &args),
+                message: format!("Unexpected line '{line}'"),
+            }))
+            .collect::<Result<_>>()?;
+
+        Ok(Box::new(entries.into_iter()))
+    }
+
+    fn remove(&self, name: &str) -> Result<()> {
+        self.remove_all(&[name])
+    }
+
+    /// Destroys the snapshots using a single `zfs destroy <dataset>@<snapshot>,<snapshot>...`.
+    fn remove_all(&self, names: &[&str]) -> Result<()> {
+        if names.is_empty() {
+            return Ok(());
+        }
+        let snapshots = format!("{}@{}", self.dataset, names.join(","));
+
+        self.runner.run("zfs", &["destroy", &snapshots]).map(|_| ())
+    }
+
+    fn batch_size(&self) -> usize {
+        BATCH_SIZE
+    }
+
+    fn rename(&self, from: &str, to: &str) -> Result<()> {
+        let from = format!("{}@{from}", self.dataset);
+        let to = format!("{}@{to}", self.dataset);
+
+        self.runner.run("zfs", &["rename", &from, &to]).map(|_| ())
+    }
+}
+
+/// Directory containing btrfs snapshots as subvolumes, removed using `btrfs subvolume delete`.
+///
+/// Files and directories which aren't subvolumes are not listed, so they are never passed to `btrfs`.
+pub struct BtrfsStorage {
+    path: PathBuf,
+    local: LocalStorage,
+    runner: Arc<dyn CommandRunner>,
+}
+
+impl BtrfsStorage {
+    /// Parses `btrfs://<directory>`, e.g. `btrfs:///.snapshots`.
+    pub fn new(url: &str) -> Result<Self> {
+        Self::with_runner(url, Arc::new(SystemRunner))
+    }
+
+    pub fn with_runner(url: &str, runner: Arc<dyn CommandRunner>) -> Result<Self> {
+        let path = PathBuf::from(url.strip_prefix("btrfs://").unwrap_or_default());
+        if path.as_os_str().is_empty() {
+            return Err(StorageError::InvalidUrl { url: url.to_string(), reason: "Missing directory".into() });
+        }
+
+        Ok(Self { local: LocalStorage::new(path.clone()), path, runner })
+    }
+}
+
+impl Storage for BtrfsStorage {
+    fn list(&self) -> Result<Box<dyn Iterator<Item = FileEntry> + '_>> {
+        let entries = self.local.list()?.filter(|entry| is_subvolume(&self.path.join(&entry.name)));
+
+        Ok(Box::new(entries))
+    }
+
+    fn remove(&self, name: &str) -> Result<()> {
+        self.remove_all(&[name])
+    }
+
+    /// Deletes the subvolumes using a single `btrfs subvolume delete <path>...`, skipping missing subvolumes.
+    fn remove_all(&self, names: &[&str]) -> Result<()> {
+        let paths: Vec<String> = names.iter()
+            .map(|name| self.path.join(name))
+            .filter(|path| path.exists())
+            .map(|path| path.to_string_lossy().to_string())
+            .collect();
+        if paths.is_empty() {
+            return Ok(());
+        }
+        let args: Vec<&str> = ["subvolume", "delete"].into_iter().chain(paths.iter().map(String::as_str)).collect();
+
+        self.runner.run("btrfs", &args).map(|_| ())
+    }
+
+    fn batch_size(&self) -> usize {
+        BATCH_SIZE
+    }
+
+    fn rename(&self, from: &str, to: &str) -> Result<()> {
+        self.local.rename(from, to)
+    }
+}
+
+#[cfg(unix)]
+fn is_subvolume(path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    fs::symlink_metadata(path).is_ok_and(|metadata| metadata.is_dir() && metadata.ino() == SUBVOLUME_INODE)
+}
+
+#[cfg(not(unix))]
+fn is_subvolume(_path: &Path) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use crate::storage::command::tests::FakeRunner;
+
+    use super::*;
+
+    const SNAPSHOTS: &str = include_str!("../../tests/fixtures/zfs-list.txt");
+
+    #[test]
+    fn zfs_list_should_parse_snapshots() {
+        let runner = Arc::new(FakeRunner::new(|_| Ok(SNAPSHOTS.to_string())));
+        let storage = ZfsStorage::with_runner("zfs://tank/data", runner.clone()).unwrap();
+
+        let entries: Vec<_> = storage.list().unwrap().collect();
+
+        let names: Vec<_> = entries.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(vec!["auto-2024-05-01_00-00", "auto-2024-05-02_00-00", "auto-2024-05-03_00-00", "before-upgrade"], names);
+        assert_eq!(Some(1048576), entries[0].size);
+        assert_eq!(DateTime::from_timestamp(1714521600, 0), entries[0].modified);
+        assert_eq!(vec!["zfs list -t snapshot -H -p -o name,used,creation -d 1 tank/data"], *runner.commands.lock().unwrap());
+    }
+
+    #[test]
+    fn zfs_list_should_report_unexpected_output() {
+        let storage = ZfsStorage::with_runner("zfs://tank/data", Arc::new(FakeRunner::new(|_| Ok("no datasets available\n".to_string())))).unwrap();
+
+        let result = storage.list();
+
+        assert!(matches!(result, Err(StorageError::Command { .. })));
+    }
+
+    #[test]
+    fn zfs_remove_all_should_destroy_snapshots_in_single_command() {
+        let runner = Arc::new(FakeRunner::new(|_| Ok(String::new())));
+        let storage = ZfsStorage::with_runner("zfs://tank/data", runner.clone()).unwrap();
+
+        storage.remove_all(&["auto-2024-05-01_00-00", "auto-2024-05-02_00-00"]).unwrap();
+
+        assert_eq!(vec!["zfs destroy tank/data@auto-2024-05-01_00-00,auto-2024-05-02_00-00"], *runner.commands.lock().unwrap());
+    }
+
+    #[test]
+    fn zfs_remove_all_should_not_run_zfs_without_snapshots() {
+        let runner = Arc::new(FakeRunner::new(|_| Ok(String::new())));
+        let storage = ZfsStorage::with_runner("zfs://tank/data", runner.clone()).unwrap();
+
+        storage.remove_all(&[]).unwrap();
+
+        assert!(runner.commands.lock().unwrap().is_empty());
+    }
+
+    #[test_case("zfs://")]
+    #[test_case("zfs://tank/data@auto-2024-05-01_00-00")]
+    fn zfs_new_should_reject_invalid_url(url: &str) {
+        let result = ZfsStorage::new(url);
+
+        assert!(matches!(result, Err(StorageError::InvalidUrl { .. })));
+    }
+
+    #[test]
+    fn btrfs_list_should_skip_entries_which_are_not_subvolumes() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("2024-05-01")).unwrap();
+        fs::write(dir.path().join("2024-05-02"), "").unwrap();
+        let storage = BtrfsStorage::with_runner(&format!("btrfs://{}", dir.path().display()), Arc::new(FakeRunner::new(|_| Ok(String::new())))).unwrap();
+
+        let entries = storage.list().unwrap().count();
+
+        assert_eq!(0, entries);
+    }
+
+    #[test]
+    fn btrfs_remove_all_should_delete_existing_subvolumes() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("2024-05-01")).unwrap();
+        let runner = Arc::new(FakeRunner::new(|_| Ok(String::new())));
+        let storage = BtrfsStorage::with_runner(&format!("btrfs://{}", dir.path().display()), runner.clone()).unwrap();
+
+        storage.remove_all(&["2024-05-01", "2024-04-30"]).unwrap();
+
+        let expected = format!("btrfs subvolume delete {}", dir.path().join("2024-05-01").display());
+        assert_eq!(vec![expected], *runner.commands.lock().unwrap());
+    }
+}
@@ -0,0 +1,367 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashMap};
+
+use chrono::{DateTime, Utc};
+
+use crate::config::RetentionPolicy;
+use crate::policy::{Period, Timestamped};
+
+/// Position of a file in the retention order: newer dates first, for equal dates later files first.
+type Rank = (DateTime<Utc>, u64);
+
+/// Applies a [RetentionPolicy] to items in arbitrary order while only keeping items in memory which can still be kept.
+///
+/// [RetentionPolicy::retain] only ever keeps the newest `keep-last` files and the latest file of the newest periods.
+/// A rule continues after the files consumed by the previous rules, which lie in the days or weeks of the files kept by them.
+/// Every period only has to remember its latest file for as many periods as these files can span plus the periods it keeps.
+/// Months are identified by the month of the year, so the latest file of as many months is remembered per month of the year.
+/// The monthly rule consumes files of any year, so the yearly rule following it has to remember every year.
+/// Files which are not tracked by any rule anymore can never be kept and are returned by [RetentionStream::push] right away.
+/// The remaining candidates are passed to [RetentionPolicy::retain] in [RetentionStream::finish].
+pub struct RetentionStream<T> {
+    policy: RetentionPolicy,
+    last: BinaryHeap<Reverse<Rank>>,
+    periods: Vec<PeriodWinners>,
+    candidates: HashMap<u64, Candidate<T>>,
+    next_id: u64,
+    untracked: usize,
+}
+
+struct PeriodWinners {
+    period: Period,
+    /// Amount of categories to remember
+    capacity: usize,
+    /// Amount of buckets to remember per category
+    buckets: usize,
+    /// Latest file per bucket by [Period::category] and [Period::bucket]
+    winners: BTreeMap<i64, BTreeMap<i64, Rank>>,
+}
+
+struct Candidate<T> {
+    file: T,
+    /// Amount of rules tracking this file
+    references: usize,
+}
+
+impl<T: Timestamped> RetentionStream<T> {
+    /// Creates an empty stream for the given policy.
+    pub fn new(policy: RetentionPolicy) -> Self {
+        // Files kept by the previous rules
+        let mut kept = policy.keep_last.unwrap_or_default();
+        // Whether the files consumed by the previous rules are only bounded by weeks instead of days
+        let mut weekly = false;
+        let mut monthly = false;
+        let periods = policy.periods()
+            .filter(|(_, count)| *count > 0)
+            .map(|(period, count)| {
+                // A week can span two months or years
+                let spanned = match period {
+                    Period::Monthly | Period::Yearly if weekly => kept * 2,
+                    _ => kept,
+                };
+                kept += count;
+                weekly |= period == Period::Weekly;
+                let (capacity, buckets) = match period {
+                    // At most 12 categories
+                    Period::Monthly => (usize::MAX, spanned + 1),
+                    Period::Yearly if monthly => (usize::MAX, 1),
+                    _ => (spanned + count, 1),
+                };
+                monthly |= period == Period::Monthly;
+
+                PeriodWinners {
+                    period,
+                    capacity,
+                    buckets,
+                    winners: BTreeMap::new(),
+                }
+            })
+            .collect();
+
+        Self {
+            policy,
+            last: BinaryHeap::new(),
+            periods,
+            candidates: HashMap::new(),
+            next_id: 0,
+            untracked: 0,
+        }
+    }
+
+    /// Adds an item and returns the items which can't be kept anymore.
+    pub fn push(&mut self, file: T) -> Vec<T> {
+        if self.policy.is_empty() {
+            self.untracked += 1;
+            return Vec::new();
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        let date = file.timestamp();
+        let rank = (date, id);
+        let mut references = 0;
+        let mut released = Vec::new();
+
+        let last = self.policy.keep_last.unwrap_or_default();
+        if self.last.len() < last {
+            self.last.push(Reverse(rank));
+            references += 1;
+        } else if self.last.peek().is_some_and(|Reverse(oldest)| *oldest < rank) {
+            if let Some(Reverse((_, oldest))) = self.last.pop() {
+                released.push(oldest);
+            }
+            self.last.push(Reverse(rank));
+            references += 1;
+        }
+
+        for period in self.periods.iter_mut() {
+            let bucket = period.period.bucket(&date);
+            let buckets = period.winners.entry(period.period.category(&date)).or_default();
+            match buckets.get_mut(&bucket) {
+                Some(winner) if *winner > rank => {}
+                Some(winner) => {
+                    released.push(winner.1);
+                    *winner = rank;
+                    references += 1;
+                }
+                None => {
+                    buckets.insert(bucket, rank);
+                    references += 1;
+                    if buckets.len() > period.buckets {
+                        if let Some((_, (_, oldest))) = buckets.pop_first() {
+                            released.push(oldest);
+                        }
+                    }
+                    // Categories of all periods except months are ordered by time
+                    if period.winners.len() > period.capacity {
+                        if let Some((_, oldest)) = period.winners.pop_first() {
+                            released.extend(oldest.into_values().map(|(_, id)| id));
+                        }
+                    }
+                }
+            }
+        }
+
+        if references == 0 {
+            return vec![file];
+        }
+        self.candidates.insert(id, Candidate { file, references });
+
+        released.into_iter()
+            .filter_map(|id| {
+                let candidate = self.candidates.get_mut(&id)?;
+                candidate.references -= 1;
+                if candidate.references == 0 {
+                    self.candidates.remove(&id).map(|candidate| candidate.file)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Amount of files kept without being tracked because the policy keeps every file.
+    pub fn untracked(&self) -> usize {
+        self.untracked
+    }
+
+    /// Applies the policy to the remaining candidates and returns the items to keep and drop.
+    pub fn finish(self) -> (Vec<T>, Vec<T>) {
+        let mut candidates: Vec<_> = self.candidates.into_iter().collect();
+        candidates.sort_by_key(|(id, candidate)| Reverse((candidate.file.timestamp(), *id)));
+        let files = candidates.into_iter().map(|(_, candidate)| candidate.file).collect();
+
+        self.policy.retain(files)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+    use test_case::test_case;
+
+    use crate::file::RetentionFile;
+
+    use super::*;
+
+    /// Generates `count` files in pseudo random order spread over the last `days` days.
+    fn files(count: usize, days: i64) -> Vec<RetentionFile> {
+        let start = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+        let mut seed: u64 = 42;
+        (0..count)
+            .map(|index| {
+                seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                let hours = (seed >> 33) as i64 % (days * 24);
+
+                RetentionFile {
+                    filename: format!("{index}"),
+                    date: start - Duration::hours(hours),
+                    ..Default::default()
+                }
+            })
+            .collect()
+    }
+
+    fn sorted(files: &[RetentionFile]) -> Vec<RetentionFile> {
+        let mut files = files.to_vec();
+        files.sort_by_key(|file| file.date);
+        files.reverse();
+        files
+    }
+
+    fn names(files: &[RetentionFile]) -> Vec<String> {
+        let mut names: Vec<_> = files.iter().map(|file| file.filename.clone()).collect();
+        names.sort();
+        names
+    }
+
+    #[test_case(RetentionPolicy { keep_last: Some(3), ..Default::default() })]
+    #[test_case(RetentionPolicy { keep_daily: Some(7), ..Default::default() })]
+    #[test_case(RetentionPolicy { keep_last: Some(1), keep_daily: Some(7), keep_weekly: Some(4), keep_monthly: Some(3), keep_yearly: Some(1), ..Default::default() })]
+    #[test_case(RetentionPolicy { keep_last: Some(20), keep_daily: Some(2), keep_monthly: Some(12), ..Default::default() })]
+    #[test_case(RetentionPolicy { keep_weekly: Some(10), keep_yearly: Some(5), ..Default::default() })]
+    #[test_case(RetentionPolicy { keep_daily: Some(0), keep_monthly: Some(2), ..Default::default() })]
+    fn stream_should_match_retain(policy: RetentionPolicy) {
+        let files = files(5000, 3 * 365);
+        let (expected_keep, expected_drop) = policy.retain(sorted(&files));
+
+        let mut stream = RetentionStream::new(policy);
+        let mut drop = Vec::new();
+        for file in files {
+            drop.extend(stream.push(file));
+        }
+        let (keep, rest) = stream.finish();
+        drop.extend(rest);
+
+        assert_eq!(expected_keep, keep);
+        assert_eq!(names(&expected_drop), names(&drop));
+    }
+
+    #[test]
+    fn stream_should_keep_file_of_month_following_week_spanning_two_months() {
+        let policy = RetentionPolicy {
+            keep_weekly: Some(2),
+            keep_monthly: Some(1),
+            ..Default::default()
+        };
+        let files: Vec<_> = ["2024-06-01", "2024-05-28", "2024-04-30", "2024-04-20", "2024-03-15"].into_iter()
+            .map(|date| RetentionFile {
+                filename: date.to_string(),
+                date: format!("{date}T00:00:00Z").parse().unwrap(),
+                ..Default::default()
+            })
+            .collect();
+        let (expected_keep, _) = policy.retain(files.clone());
+
+        let mut stream = RetentionStream::new(policy);
+        for file in files {
+            stream.push(file);
+        }
+        let (keep, _) = stream.finish();
+
+        assert_eq!(vec!["2024-03-15", "2024-04-30", "2024-06-01"], names(&expected_keep));
+        assert_eq!(names(&expected_keep), names(&keep));
+    }
+
+    #[test]
+    fn stream_should_count_same_month_of_different_years_once() {
+        let policy = RetentionPolicy {
+            keep_monthly: Some(2),
+            ..Default::default()
+        };
+        let mut stream = RetentionStream::new(policy);
+        let mut drop = Vec::new();
+        for date in ["2023-04-10", "2024-05-01", "2023-05-20"] {
+            drop.extend(stream.push(RetentionFile {
+                filename: date.to_string(),
+                date: format!("{date}T00:00:00Z").parse().unwrap(),
+                ..Default::default()
+            }));
+        }
+
+        let (keep, rest) = stream.finish();
+        drop.extend(rest);
+
+        assert_eq!(vec!["2023-04-10", "2024-05-01"], names(&keep));
+        assert_eq!(vec!["2023-05-20"], names(&drop));
+    }
+
+    /// Random policies with small counts on few files, so many periods are empty and weeks span months with few files.
+    #[test]
+    fn stream_should_match_retain_for_sparse_files() {
+        let mut seed: u64 = 7;
+        let mut random = |max: usize| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (seed >> 33) as usize % max
+        };
+        // Unset in a third of the policies, otherwise 0 to 3
+        let count = |random: &mut dyn FnMut(usize) -> usize| Some(random(4)).filter(|_| random(3) > 0);
+        for _ in 0..2000 {
+            let policy = RetentionPolicy {
+                keep_last: count(&mut random),
+                keep_daily: count(&mut random),
+                keep_weekly: count(&mut random),
+                keep_monthly: count(&mut random),
+                keep_yearly: count(&mut random),
+                ..Default::default()
+            };
+            if policy.is_empty() {
+                continue;
+            }
+            let days = [20, 60, 400, 3000][random(4)];
+            let start = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+            let files: Vec<_> = (0..1 + random(20))
+                .map(|index| RetentionFile {
+                    filename: format!("{index}"),
+                    date: start - Duration::hours(random(days * 24) as i64),
+                    ..Default::default()
+                })
+                .collect();
+            let (expected_keep, expected_drop) = policy.retain(sorted(&files));
+
+            let mut stream = RetentionStream::new(policy);
+            let mut drop = Vec::new();
+            for file in files {
+                drop.extend(stream.push(file));
+            }
+            let (keep, rest) = stream.finish();
+            drop.extend(rest);
+
+            assert_eq!(names(&expected_keep), names(&keep), "{policy:?}");
+            assert_eq!(names(&expected_drop), names(&drop), "{policy:?}");
+        }
+    }
+
+    #[test]
+    fn stream_should_only_track_candidates() {
+        let policy = RetentionPolicy {
+            keep_last: Some(2),
+            keep_daily: Some(7),
+            keep_monthly: Some(3),
+            ..Default::default()
+        };
+        let mut stream = RetentionStream::new(policy);
+
+        for file in files(100_000, 10 * 365) {
+            stream.push(file);
+        }
+
+        // The last 10 occurrences of every month of the year can be kept after the files of 9 days
+        assert!(stream.candidates.len() <= 2 + 9 + 12 * 10);
+    }
+
+    #[test_case(RetentionPolicy::default())]
+    #[test_case(RetentionPolicy { keep_daily: Some(0), keep_monthly: Some(0), ..Default::default() })]
+    fn stream_should_keep_everything_for_empty_policy(policy: RetentionPolicy) {
+        let mut stream = RetentionStream::new(policy);
+
+        for file in files(100, 10) {
+            assert!(stream.push(file).is_empty());
+        }
+        let stream_untracked = stream.untracked();
+        let (keep, drop) = stream.finish();
+
+        assert_eq!(100, stream_untracked);
+        assert!(keep.is_empty());
+        assert!(drop.is_empty());
+    }
+}